@@ -0,0 +1,131 @@
+use openssl::rand::rand_bytes;
+use rustc_serialize::hex::ToHex;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/**
+ * Default lifetime of tokens given to modules (in seconds)
+ */
+pub const DEFAULT_TOKEN_TTL: u64 = 300;
+
+/**
+ * What a client can do with the HTTP API
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ApiScope {
+    /// Get endpoints, words and stats
+    Read,
+    /// Send data to endpoints and reprocess data
+    Send,
+    /// Add and remove words
+    Words,
+    /// Everything, including removing endpoints
+    Admin,
+}
+
+impl ApiScope {
+    pub fn from_str(scope: &str) -> Option<ApiScope> {
+        match scope {
+            "read" => Some(ApiScope::Read),
+            "send" => Some(ApiScope::Send),
+            "words" => Some(ApiScope::Words),
+            "admin" => Some(ApiScope::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ApiScope::Read => "read",
+            ApiScope::Send => "send",
+            ApiScope::Words => "words",
+            ApiScope::Admin => "admin",
+        }
+    }
+
+    /**
+     * @param scopes: the scopes of a client
+     * @return if these scopes allow this one
+     */
+    pub fn granted_by(&self, scopes: &[ApiScope]) -> bool {
+        scopes.iter().any(|scope| scope == self || *scope == ApiScope::Admin)
+    }
+}
+
+/**
+ * Scopes of tokens given to modules, if the client of the data has them
+ */
+const MODULE_SCOPES: [ApiScope; 3] = [ApiScope::Read, ApiScope::Send, ApiScope::Words];
+
+/**
+ * @param scopes: the scopes of the client of the data
+ * @return the scopes of the token given to modules processing this data
+ */
+pub fn module_scopes(scopes: &[ApiScope]) -> Vec<ApiScope> {
+    MODULE_SCOPES.iter().cloned().filter(|scope| scope.granted_by(scopes)).collect()
+}
+
+struct Token {
+    client: String,
+    scopes: Vec<ApiScope>,
+    expire: Instant,
+}
+
+/**
+ * Short-lived tokens, given to modules to call the API
+ */
+pub struct TokenManager {
+    tokens: Mutex<HashMap<String, Token>>,
+}
+
+impl TokenManager {
+    pub fn new() -> TokenManager {
+        TokenManager { tokens: Mutex::new(HashMap::new()) }
+    }
+
+    /**
+     * Create a token
+     * @param client: the client the token acts for
+     * @param scopes: what the token allows
+     * @param ttl: how long the token is valid
+     * @return the token
+     */
+    pub fn issue(&self, client: &str, scopes: Vec<ApiScope>, ttl: Duration) -> String {
+        let mut random = [0; 32];
+        rand_bytes(&mut random).unwrap();
+        let token = random.to_hex();
+        let mut tokens = self.tokens.lock().unwrap();
+        // Forget expired tokens
+        let now = Instant::now();
+        tokens.retain(|_, token| token.expire > now);
+        tokens.insert(token.clone(),
+                      Token {
+                          client: String::from(client),
+                          scopes: scopes,
+                          expire: now + ttl,
+                      });
+        token
+    }
+
+    /**
+     * Invalidate a token before it expires
+     */
+    pub fn revoke(&self, token: &str) {
+        self.tokens.lock().unwrap().remove(token);
+    }
+
+    /**
+     * @param token: the token sent by a client
+     * @return the client and scopes of the token if valid
+     */
+    pub fn check(&self, token: &str) -> Option<(String, Vec<ApiScope>)> {
+        let tokens = self.tokens.lock().unwrap();
+        match tokens.get(token) {
+            Some(token) if token.expire > Instant::now() => {
+                Some((token.client.clone(), token.scopes.clone()))
+            }
+            _ => None,
+        }
+    }
+}
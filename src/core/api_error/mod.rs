@@ -0,0 +1,58 @@
+use iron::prelude::*;
+use iron::headers::ContentType;
+use iron::modifiers::Header;
+use iron::status::Status;
+use rustc_serialize::json::{self, decode};
+
+/**
+ * An error returned by the API, as {"error":{"status":404,"code":"not_found","message":"..."}}
+ */
+#[derive(Clone, RustcDecodable, RustcEncodable, PartialEq, Debug)]
+pub struct ApiError {
+    /// the HTTP status
+    pub status: u16,
+    /// the HTTP status as snake_case, like not_found
+    pub code: String,
+    /// what went wrong
+    pub message: String,
+}
+
+#[derive(RustcDecodable, RustcEncodable)]
+struct ErrorBody {
+    error: ApiError,
+}
+
+impl ApiError {
+    pub fn new(status: Status, message: &str) -> ApiError {
+        let reason = status.canonical_reason().unwrap_or("error");
+        ApiError {
+            status: status.to_u16(),
+            code: reason.to_lowercase().replace(&[' ', '-'][..], "_"),
+            message: String::from(message),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        json::encode(&ErrorBody { error: self.clone() }).unwrap()
+    }
+
+    pub fn from_json(data: &str) -> Option<ApiError> {
+        decode::<ErrorBody>(data).ok().map(|body| body.error)
+    }
+
+    /**
+     * @return the error as a JSON response
+     */
+    pub fn response(&self) -> Response {
+        json_response(Status::from_u16(self.status), self.to_json())
+    }
+}
+
+/**
+ * @param status: the HTTP status
+ * @param body: the JSON to send
+ * @return a response with a JSON content type
+ */
+pub fn json_response(status: Status, body: String) -> Response {
+    Response::with((status, Header(ContentType::json()), body))
+}
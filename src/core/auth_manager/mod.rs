@@ -0,0 +1,384 @@
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::pbkdf2::pbkdf2;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+use core::api_auth::ApiScope;
+use core::rate_limiter::RateLimit;
+use core::envelope::{self, Envelope, NonceCache, DEFAULT_MAX_CLOCK_SKEW, DEFAULT_NONCE_CACHE_SIZE};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::rand::rand_bytes;
+use openssl::x509::X509Ref;
+use rori_utils::data::RoriData;
+use rustc_serialize::hex::{FromHex, ToHex};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * Default cost of PBKDF2 for new secrets
+ */
+pub const DEFAULT_ITERATIONS: u32 = 20000;
+const PBKDF2_SHA256: &'static str = "pbkdf2-sha256";
+
+#[derive(Clone, RustcDecodable, RustcEncodable, Default, PartialEq, Debug)]
+pub struct AuthorizedUser {
+    pub name: Option<String>,
+    /// Hash of the secret: $pbkdf2-sha256$<iterations>$<salt>$<hash> (or a deprecated SHA-256)
+    pub secret: Option<String>,
+    /// Other accepted hashes, used during a secret rotation
+    pub secrets: Option<Vec<String>>,
+    /// Unix timestamp after which secrets are not accepted anymore
+    pub secrets_expire: Option<String>,
+    /// Common name of the client certificate
+    pub cert_subject: Option<String>,
+    /// SHA-256 fingerprint of the client certificate
+    pub cert_fingerprint: Option<String>,
+    /// Datatypes this client can send (all if None)
+    pub datatypes: Option<Vec<String>>,
+    /// If this client can register endpoints (true if None)
+    pub can_register: Option<bool>,
+    /// Authors this client can speak for (all if None, "*" matches everyone)
+    pub owners: Option<Vec<String>>,
+    /// Key used to sign envelopes
+    pub signing_key: Option<String>,
+    /// If true, data from this client must be in a signed envelope
+    pub require_signature: Option<bool>,
+    /// How many messages this client can send
+    pub rate_limit: Option<RateLimit>,
+    /// How many messages each author can send through this client
+    pub author_rate_limit: Option<RateLimit>,
+    /// What this client can do with the HTTP API: read, send, words, admin (nothing if None)
+    pub api_scopes: Option<Vec<String>>,
+}
+
+impl AuthorizedUser {
+    /**
+     * @return the valid scopes of this client on the HTTP API
+     */
+    pub fn api_scopes(&self) -> Vec<ApiScope> {
+        self.api_scopes
+            .clone()
+            .unwrap_or(Vec::new())
+            .iter()
+            .filter_map(|scope| ApiScope::from_str(scope))
+            .collect()
+    }
+
+    /**
+     * Check the scopes of this client
+     * @param data: the data sent by this client
+     * @return if this client is allowed to send this data
+     */
+    pub fn can_send(&self, data: &RoriData) -> bool {
+        if let Some(ref owners) = self.owners {
+            if !owners.iter().any(|owner| owner == "*" || *owner == data.author) {
+                return false;
+            }
+        }
+        // register, heartbeat and unregister are only controlled by can_register
+        if ["register", "heartbeat", "unregister"].contains(&&*data.datatype) {
+            return self.can_register.unwrap_or(true);
+        }
+        match self.datatypes {
+            Some(ref datatypes) => datatypes.contains(&data.datatype),
+            None => true,
+        }
+    }
+}
+
+/**
+ * What we know about a client authenticated with a certificate
+ */
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct PeerIdentity {
+    pub subject: Option<String>,
+    pub fingerprint: String,
+}
+
+impl PeerIdentity {
+    /**
+     * @param cert: the certificate presented by the client
+     * @return the identity of the client
+     */
+    pub fn from_certificate(cert: &X509Ref) -> PeerIdentity {
+        let subject = cert.subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .map(|entry| String::from_utf8_lossy(entry.data().as_slice()).into_owned());
+        let fingerprint = match cert.digest(MessageDigest::sha256()) {
+            Ok(digest) => digest.iter().map(|b| format!("{:02x}", b)).collect(),
+            Err(_) => String::from(""),
+        };
+        PeerIdentity {
+            subject: subject,
+            fingerprint: fingerprint,
+        }
+    }
+}
+
+/**
+ * Decide if a client can talk to RORI
+ */
+#[derive(Clone)]
+pub struct AuthManager {
+    authorize: Vec<AuthorizedUser>,
+    max_clock_skew: u64,
+    nonces: Arc<Mutex<NonceCache>>,
+}
+
+impl AuthManager {
+    pub fn new(authorize: Vec<AuthorizedUser>) -> AuthManager {
+        for user in &authorize {
+            let mut hashes = user.secrets.clone().unwrap_or(Vec::new());
+            hashes.extend(user.secret.clone());
+            if hashes.iter().any(|hash| !hash.starts_with("$")) {
+                warn!(target:"AuthManager",
+                      "{} uses an unsalted SHA-256 secret. This is deprecated, please generate a \
+                       new one with --hash-secret",
+                      user.name.clone().unwrap_or(String::from("")));
+            }
+        }
+        AuthManager {
+            authorize: authorize,
+            max_clock_skew: DEFAULT_MAX_CLOCK_SKEW,
+            nonces: Arc::new(Mutex::new(NonceCache::new(DEFAULT_NONCE_CACHE_SIZE))),
+        }
+    }
+
+    /**
+     * Configure how signed envelopes are checked
+     * @param max_clock_skew: maximum age of an envelope (in seconds)
+     * @param nonce_cache_size: how many nonces are remembered
+     */
+    pub fn set_replay_protection(&mut self, max_clock_skew: u64, nonce_cache_size: usize) {
+        self.max_clock_skew = max_clock_skew;
+        self.nonces = Arc::new(Mutex::new(NonceCache::new(nonce_cache_size)));
+    }
+
+    /**
+     * Share the nonces seen by a previous AuthManager, so a reload can't be used to replay envelopes
+     * @param previous: the AuthManager to replace
+     */
+    pub fn keep_nonces(&mut self, previous: &AuthManager) {
+        self.nonces = previous.nonces.clone();
+    }
+
+    /**
+     * Find the user described by a client certificate
+     * @param peer: the identity of the client
+     * @return the user if the certificate is mapped
+     */
+    pub fn user_for_peer(&self, peer: &PeerIdentity) -> Option<&AuthorizedUser> {
+        let fingerprint = AuthManager::normalize_fingerprint(&*peer.fingerprint);
+        for user in &self.authorize {
+            if let Some(ref expected) = user.cert_fingerprint {
+                if fingerprint.len() > 0 && AuthManager::normalize_fingerprint(expected) == fingerprint {
+                    return Some(user);
+                }
+            }
+            if let (&Some(ref expected), &Some(ref subject)) = (&user.cert_subject, &peer.subject) {
+                if expected == subject {
+                    return Some(user);
+                }
+            }
+        }
+        None
+    }
+
+    /**
+     * Find which client sent a data
+     * @param data: the data received
+     * @param peer: the identity of the client if it sent a certificate
+     * @return the client if authenticated
+     */
+    pub fn authenticate(&self, data: &RoriData, peer: Option<&PeerIdentity>) -> Option<&AuthorizedUser> {
+        // A client authenticated with a certificate doesn't need a secret, but can only speak
+        // for itself.
+        if let Some(user) = peer.and_then(|peer| self.user_for_peer(peer)) {
+            let name = user.name.clone().unwrap_or(String::from(""));
+            if name.to_lowercase() == data.client.to_lowercase() {
+                return Some(user);
+            }
+            return None;
+        }
+        for client in &self.authorize {
+            let name = client.name.clone().unwrap_or(String::from(""));
+            if name.to_lowercase() != data.client.to_lowercase() {
+                continue;
+            }
+            if client.require_signature.unwrap_or(false) {
+                error!(target:"AuthManager", "{} must sign its data", name);
+                return None;
+            }
+            if AuthManager::check_secret(client, &*data.secret) {
+                return Some(client);
+            }
+        }
+        None
+    }
+
+    /**
+     * Find which client uses the HTTP API
+     * @param name: the name of the client
+     * @param secret: the secret of the client
+     * @return the client if authenticated
+     */
+    pub fn authenticate_api(&self, name: &str, secret: &str) -> Option<&AuthorizedUser> {
+        self.authorize.iter().find(|client| {
+            let client_name = client.name.clone().unwrap_or(String::from(""));
+            client_name.to_lowercase() == name.to_lowercase() &&
+            AuthManager::check_secret(client, secret)
+        })
+    }
+
+    /**
+     * @return if secret is the secret of the client (or an old one during a rotation)
+     */
+    fn check_secret(client: &AuthorizedUser, secret: &str) -> bool {
+        if client.secret.iter().any(|hash| verify_secret(hash, secret)) {
+            return true;
+        }
+        if AuthManager::rotation_window_open(client) {
+            let secrets = client.secrets.clone().unwrap_or(Vec::new());
+            return secrets.iter().any(|hash| verify_secret(hash, secret));
+        }
+        false
+    }
+
+    /**
+     * Find which client signed an envelope. Reject replayed and too old envelopes.
+     * @param envelope: the envelope received
+     * @param data: the RoriData in the envelope
+     * @return the client if the signature is valid
+     */
+    pub fn authenticate_envelope(&self, envelope: &Envelope, data: &RoriData) -> Option<&AuthorizedUser> {
+        if envelope.client.to_lowercase() != data.client.to_lowercase() {
+            error!(target:"AuthManager", "Envelope from {} contains data from {}", envelope.client, data.client);
+            return None;
+        }
+        let user = self.authorize.iter().find(|user| {
+            user.name.clone().unwrap_or(String::from("")).to_lowercase() ==
+            envelope.client.to_lowercase()
+        });
+        let user = match user {
+            Some(user) => user,
+            None => return None,
+        };
+        let verified = match user.signing_key {
+            Some(ref key) => envelope.verify(key),
+            None => false,
+        };
+        if !verified {
+            error!(target:"AuthManager", "Invalid signature from {}", envelope.client);
+            return None;
+        }
+        let now = envelope::now();
+        let age = if now > envelope.timestamp {
+            now - envelope.timestamp
+        } else {
+            envelope.timestamp - now
+        };
+        if age > self.max_clock_skew {
+            error!(target:"AuthManager", "Envelope from {} is outside the clock skew window", envelope.client);
+            return None;
+        }
+        let nonce = format!("{}:{}", envelope.client.to_lowercase(), envelope.nonce);
+        let oldest = now.saturating_sub(self.max_clock_skew);
+        if !self.nonces.lock().unwrap().insert(&*nonce, envelope.timestamp, oldest) {
+            error!(target:"AuthManager", "Replayed envelope from {} or too many recent envelopes",
+                   envelope.client);
+            return None;
+        }
+        Some(user)
+    }
+
+    /**
+     * @param data: the data received
+     * @param peer: the identity of the client if it sent a certificate
+     * @return if the data comes from a known client
+     */
+    pub fn is_authorized(&self, data: &RoriData, peer: Option<&PeerIdentity>) -> bool {
+        self.authenticate(data, peer).is_some()
+    }
+
+    /**
+     * @return if secrets (used during a rotation) are still valid
+     */
+    fn rotation_window_open(client: &AuthorizedUser) -> bool {
+        let expire = match client.secrets_expire {
+            Some(ref expire) => expire,
+            None => return true,
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        match expire.parse::<u64>() {
+            Ok(expire) => now < expire,
+            Err(_) => {
+                error!(target:"AuthManager", "Invalid secrets_expire: {}", expire);
+                false
+            }
+        }
+    }
+
+    pub fn normalize_fingerprint(fingerprint: &str) -> String {
+        fingerprint.replace(":", "").to_lowercase()
+    }
+}
+
+fn pbkdf2_sha256(secret: &str, salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut mac = Hmac::new(Sha256::new(), secret.as_bytes());
+    let mut result = vec![0u8; 32];
+    pbkdf2(&mut mac, salt, iterations, &mut result);
+    result
+}
+
+/**
+ * Hash a secret with a random salt
+ * @param secret: the secret to hash
+ * @param iterations: the cost of PBKDF2
+ * @return $pbkdf2-sha256$<iterations>$<salt>$<hash>
+ */
+pub fn hash_secret(secret: &str, iterations: u32) -> String {
+    let mut salt = [0u8; 16];
+    rand_bytes(&mut salt).unwrap();
+    let hash = pbkdf2_sha256(secret, &salt, iterations);
+    format!("${}${}${}${}",
+            PBKDF2_SHA256,
+            iterations,
+            salt.to_hex(),
+            hash.to_hex())
+}
+
+/**
+ * Check a secret against a hash
+ * @param hash: the hash from the configuration
+ * @param secret: the secret sent by a client
+ * @return if the secret is correct
+ */
+pub fn verify_secret(hash: &str, secret: &str) -> bool {
+    if !hash.starts_with("$") {
+        // Deprecated: unsalted SHA-256
+        let mut hasher = Sha256::new();
+        hasher.input_str(secret);
+        let expected = hash.to_lowercase();
+        return fixed_time_eq(hasher.result_str().as_bytes(), expected.as_bytes());
+    }
+    let parts: Vec<&str> = hash.split('$').collect();
+    if parts.len() != 5 || parts[1] != PBKDF2_SHA256 {
+        error!(target:"AuthManager", "Unknown secret format");
+        return false;
+    }
+    let iterations = parts[2].parse::<u32>();
+    let salt = parts[3].from_hex();
+    let expected = parts[4].from_hex();
+    match (iterations, salt, expected) {
+        (Ok(iterations), Ok(salt), Ok(expected)) if iterations > 0 => {
+            let hash = pbkdf2_sha256(secret, &salt, iterations);
+            fixed_time_eq(&hash, &expected)
+        }
+        _ => {
+            error!(target:"AuthManager", "Invalid secret hash");
+            false
+        }
+    }
+}
@@ -0,0 +1,557 @@
+use core::api_auth::ApiScope;
+use core::auth_manager::AuthorizedUser;
+use core::delivery::DEFAULT_DELIVERY_RETRIES;
+use core::endpoint_manager::{DEFAULT_ENDPOINT_LEASE, MAX_ENDPOINT_LEASE};
+use core::envelope::{DEFAULT_MAX_CLOCK_SKEW, DEFAULT_NONCE_CACHE_SIZE};
+use core::framing::DEFAULT_MAX_FRAME_SIZE;
+use core::webhooks::{DEFAULT_WEBHOOK_RETRIES, MAX_WEBHOOK_RETRIES, WEBHOOK_EVENTS, Webhook};
+use core::worker_pool::{DEFAULT_QUEUE_SIZE, DEFAULT_WORKERS, OverflowPolicy};
+use rustc_serialize::json::decode;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::prelude::*;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/**
+ * Default time given to in-flight messages when stopping (in seconds)
+ */
+pub const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 30;
+/**
+ * Maximum time given to in-flight messages when stopping (in seconds)
+ */
+pub const MAX_SHUTDOWN_TIMEOUT: u64 = 3600;
+/**
+ * Default number of clients connected at the same time
+ */
+pub const DEFAULT_MAX_CONNECTIONS: usize = 256;
+
+/**
+ * An entry of the listeners list, as written by the user
+ */
+#[derive(Clone, RustcDecodable, RustcEncodable, Default, PartialEq, Debug)]
+struct RawListener {
+    pub transport: Option<String>,
+    pub address: Option<String>,
+    pub path: Option<String>,
+    pub mode: Option<String>,
+}
+
+/**
+ * An entry of the webhooks list, as written by the user
+ */
+#[derive(Clone, RustcDecodable, RustcEncodable, Default, PartialEq, Debug)]
+struct RawWebhook {
+    pub url: Option<String>,
+    pub secret: Option<String>,
+    pub events: Option<Vec<String>>,
+}
+
+/**
+ * Where the Server accepts clients
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub enum Listener {
+    /// TLS over TCP (IPv4 or IPv6)
+    Tls(SocketAddr),
+    /// Plaintext TCP, only on a loopback address
+    Plain(SocketAddr),
+    /// Unix domain socket, protected by its permissions (mode in octal)
+    Unix(PathBuf, Option<u32>),
+    /// WebSocket over TLS
+    Wss(SocketAddr),
+    /// Plaintext WebSocket, only on a loopback address (behind a proxy)
+    Ws(SocketAddr),
+}
+
+/**
+ * config_server.json as written by the user. Every value is a string.
+ */
+#[derive(Clone, RustcDecodable, RustcEncodable, Default, PartialEq, Debug)]
+struct RawConfig {
+    pub ip: Option<String>,
+    pub port: Option<String>,
+    pub api_ip: Option<String>,
+    pub api_port: Option<String>,
+    pub cert: Option<String>,
+    pub key: Option<String>,
+    pub client_ca: Option<String>,
+    pub client_auth: Option<String>,
+    pub max_frame_size: Option<String>,
+    pub max_clock_skew: Option<String>,
+    pub nonce_cache_size: Option<String>,
+    pub workers: Option<String>,
+    pub queue_size: Option<String>,
+    pub overflow: Option<String>,
+    pub max_connections: Option<String>,
+    pub shutdown_timeout: Option<String>,
+    pub endpoints_file: Option<String>,
+    pub endpoint_lease: Option<String>,
+    pub delivery_retries: Option<String>,
+    pub dead_letters_file: Option<String>,
+    pub webhook_retries: Option<String>,
+    pub listeners: Option<Vec<RawListener>>,
+    pub webhooks: Option<Vec<RawWebhook>>,
+    pub authorize: Option<Vec<AuthorizedUser>>,
+}
+
+impl RawConfig {
+    /**
+     * Replace values by RORI_<FIELD> environment variables (for example RORI_PORT)
+     * @param vars: the environment
+     */
+    fn apply_env(&mut self, vars: &HashMap<String, String>) {
+        let fields: Vec<(&str, &mut Option<String>)> = vec![("IP", &mut self.ip),
+                                                             ("PORT", &mut self.port),
+                                                             ("API_IP", &mut self.api_ip),
+                                                             ("API_PORT", &mut self.api_port),
+                                                             ("CERT", &mut self.cert),
+                                                             ("KEY", &mut self.key),
+                                                             ("CLIENT_CA", &mut self.client_ca),
+                                                             ("CLIENT_AUTH", &mut self.client_auth),
+                                                             ("MAX_FRAME_SIZE", &mut self.max_frame_size),
+                                                             ("MAX_CLOCK_SKEW", &mut self.max_clock_skew),
+                                                             ("NONCE_CACHE_SIZE", &mut self.nonce_cache_size),
+                                                             ("WORKERS", &mut self.workers),
+                                                             ("QUEUE_SIZE", &mut self.queue_size),
+                                                             ("OVERFLOW", &mut self.overflow),
+                                                             ("MAX_CONNECTIONS", &mut self.max_connections),
+                                                             ("SHUTDOWN_TIMEOUT", &mut self.shutdown_timeout),
+                                                             ("ENDPOINTS_FILE", &mut self.endpoints_file),
+                                                             ("ENDPOINT_LEASE", &mut self.endpoint_lease),
+                                                             ("DELIVERY_RETRIES", &mut self.delivery_retries),
+                                                             ("DEAD_LETTERS_FILE", &mut self.dead_letters_file),
+                                                             ("WEBHOOK_RETRIES", &mut self.webhook_retries)];
+        for (name, field) in fields {
+            if let Some(value) = vars.get(&format!("RORI_{}", name)) {
+                *field = Some(value.clone());
+            }
+        }
+    }
+}
+
+/**
+ * Every problem found in a configuration
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problems.join("\n"))
+    }
+}
+
+impl Error for ConfigError {
+    fn description(&self) -> &str {
+        "invalid configuration"
+    }
+}
+
+/**
+ * Configuration of the Server and the API
+ */
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub listeners: Vec<Listener>,
+    pub api_address: SocketAddr,
+    /// Certificate and key of TLS listeners
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub client_ca: Option<PathBuf>,
+    pub require_client_cert: bool,
+    pub max_frame_size: usize,
+    pub max_clock_skew: Duration,
+    pub nonce_cache_size: usize,
+    pub workers: usize,
+    pub queue_size: usize,
+    pub overflow: OverflowPolicy,
+    pub max_connections: usize,
+    pub shutdown_timeout: Duration,
+    pub endpoints_file: Option<PathBuf>,
+    pub endpoint_lease: Duration,
+    pub delivery_retries: u64,
+    pub dead_letters_file: Option<PathBuf>,
+    pub webhooks: Vec<Webhook>,
+    pub webhook_retries: u64,
+    pub authorize: Vec<AuthorizedUser>,
+}
+
+/**
+ * Collect problems while reading a RawConfig
+ */
+struct Validator {
+    problems: Vec<String>,
+    check_files: bool,
+}
+
+impl Validator {
+    fn required(&mut self, name: &str, value: &Option<String>) -> Option<String> {
+        match *value {
+            Some(ref value) if value.len() > 0 => Some(value.clone()),
+            _ => {
+                self.problems.push(format!("{} is missing", name));
+                None
+            }
+        }
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str, value: &Option<String>, default: T) -> T {
+        match *value {
+            Some(ref value) => {
+                match value.parse::<T>() {
+                    Ok(value) => value,
+                    Err(_) => {
+                        self.problems.push(format!("{}: \"{}\" is not valid", name, value));
+                        default
+                    }
+                }
+            }
+            None => default,
+        }
+    }
+
+    fn positive(&mut self, name: &str, value: &Option<String>, default: usize) -> usize {
+        let result = self.parse(name, value, default);
+        if result == 0 {
+            self.problems.push(format!("{} must be greater than 0", name));
+        }
+        result
+    }
+
+    fn at_most(&mut self, name: &str, value: &Option<String>, default: u64, max: u64) -> u64 {
+        let result = self.parse(name, value, default);
+        if result > max {
+            self.problems.push(format!("{} must be at most {}", name, max));
+        }
+        result
+    }
+
+    fn address(&mut self, ip_name: &str, ip: &Option<String>, port_name: &str, port: &Option<String>)
+               -> Option<SocketAddr> {
+        let ip = self.required(ip_name, ip).and_then(|ip| match ip.parse::<IpAddr>() {
+            Ok(ip) => Some(ip),
+            Err(_) => {
+                self.problems.push(format!("{}: \"{}\" is not an IP address", ip_name, ip));
+                None
+            }
+        });
+        let port = self.required(port_name, port).and_then(|port| match port.parse::<u16>() {
+            Ok(port) => Some(port),
+            Err(_) => {
+                self.problems.push(format!("{}: \"{}\" is not a port", port_name, port));
+                None
+            }
+        });
+        match (ip, port) {
+            (Some(ip), Some(port)) => Some(SocketAddr::new(ip, port)),
+            _ => None,
+        }
+    }
+
+    fn listener(&mut self, index: usize, raw: &RawListener) -> Option<Listener> {
+        let name = format!("listeners[{}]", index);
+        let transport = raw.transport.clone().unwrap_or(String::from("tls"));
+        if transport == "unix" {
+            let path = self.required(&*format!("{}.path", name), &raw.path);
+            let mode = raw.mode.clone().and_then(|mode| match u32::from_str_radix(&*mode, 8) {
+                Ok(mode) => Some(mode),
+                Err(_) => {
+                    self.problems.push(format!("{}.mode: \"{}\" is not an octal mode", name, mode));
+                    None
+                }
+            });
+            return path.map(|path| Listener::Unix(PathBuf::from(path), mode));
+        }
+        let address = self.required(&*format!("{}.address", name), &raw.address)
+            .and_then(|address| match address.parse::<SocketAddr>() {
+                Ok(address) => Some(address),
+                Err(_) => {
+                    self.problems.push(format!("{}.address: \"{}\" is not a socket address",
+                                               name,
+                                               address));
+                    None
+                }
+            });
+        // Plaintext is only accepted from the same host
+        if transport == "tcp" || transport == "ws" {
+            if let Some(address) = address {
+                if !address.ip().is_loopback() {
+                    self.problems.push(format!("{}.address: plaintext listeners must use a \
+                                                loopback address",
+                                               name));
+                }
+            }
+        }
+        match &*transport {
+            "tls" => address.map(Listener::Tls),
+            "tcp" => address.map(Listener::Plain),
+            "wss" => address.map(Listener::Wss),
+            "ws" => address.map(Listener::Ws),
+            _ => {
+                self.problems.push(format!("{}.transport: \"{}\" must be tls, tcp, unix, wss or \
+                                            ws",
+                                           name,
+                                           transport));
+                None
+            }
+        }
+    }
+
+    fn file(&mut self, name: &str, path: String) -> PathBuf {
+        let path = PathBuf::from(path);
+        if self.check_files && !path.is_file() {
+            self.problems.push(format!("{}: {} doesn't exist", name, path.display()));
+        }
+        path
+    }
+
+    fn authorize(&mut self, authorize: &Option<Vec<AuthorizedUser>>) -> Vec<AuthorizedUser> {
+        let authorize = authorize.clone().unwrap_or(Vec::new());
+        let mut names = HashSet::new();
+        for (index, user) in authorize.iter().enumerate() {
+            match user.name {
+                Some(ref name) if name.len() > 0 => {
+                    // Clients are compared without case
+                    if !names.insert(name.to_lowercase()) {
+                        self.problems.push(format!("authorize: {} is defined twice", name));
+                    }
+                }
+                _ => self.problems.push(format!("authorize: client #{} has no name", index)),
+            }
+            // A limit of 0 would reject every message
+            for &(name, limit) in &[("rate_limit", &user.rate_limit),
+                                    ("author_rate_limit", &user.author_rate_limit)] {
+                if let Some(ref limit) = *limit {
+                    if limit.rate.is_nan() || limit.rate <= 0. {
+                        self.problems.push(format!("authorize: client #{} {}.rate must be greater \
+                                                    than 0",
+                                                   index,
+                                                   name));
+                    }
+                    if limit.burst.is_nan() || limit.burst < 1. {
+                        self.problems.push(format!("authorize: client #{} {}.burst must be at \
+                                                    least 1",
+                                                   index,
+                                                   name));
+                    }
+                }
+            }
+            for scope in user.api_scopes.clone().unwrap_or(Vec::new()) {
+                if ApiScope::from_str(&*scope).is_none() {
+                    self.problems.push(format!("authorize: client #{} has an unknown api scope \"{}\"",
+                                               index,
+                                               scope));
+                }
+            }
+        }
+        authorize
+    }
+
+    fn webhook(&mut self, index: usize, webhook: &RawWebhook) -> Option<Webhook> {
+        let name = format!("webhooks #{}", index);
+        let url = match webhook.url {
+            Some(ref url) if url.starts_with("http://") || url.starts_with("https://") => url.clone(),
+            Some(ref url) => {
+                self.problems.push(format!("{}: \"{}\" is not an http or https URL", name, url));
+                return None;
+            }
+            None => {
+                self.problems.push(format!("{}: url is missing", name));
+                return None;
+            }
+        };
+        let secret = self.required(&*format!("{}: secret", name), &webhook.secret)?;
+        let events = match webhook.events {
+            Some(ref events) if events.len() > 0 => events.clone(),
+            // Every event by default
+            _ => WEBHOOK_EVENTS.iter().map(|event| String::from(*event)).collect(),
+        };
+        for event in &events {
+            if !WEBHOOK_EVENTS.contains(&&**event) {
+                self.problems.push(format!("{}: unknown event \"{}\"", name, event));
+            }
+        }
+        Some(Webhook {
+            url: url,
+            secret: secret,
+            events: events,
+        })
+    }
+}
+
+impl Config {
+    /**
+     * Read a configuration and check it, without checking files it references
+     * @param path: path of config_server.json
+     * @return the configuration or every problem found
+     */
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        Config::build(path.as_ref(), false, &env::vars().collect())
+    }
+
+    /**
+     * Like read, with RORI_* variables taken from vars instead of the environment
+     * @param path: path of config_server.json
+     * @param vars: the variables overriding values of the file
+     * @return the configuration or every problem found
+     */
+    pub fn read_with_env<P: AsRef<Path>>(path: P, vars: &HashMap<String, String>)
+                                         -> Result<Config, ConfigError> {
+        Config::build(path.as_ref(), false, vars)
+    }
+
+    /**
+     * Read a configuration and check it, including certificates and keys
+     * @param path: path of config_server.json
+     * @return the configuration or every problem found
+     */
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        Config::build(path.as_ref(), true, &env::vars().collect())
+    }
+
+    fn build(path: &Path, check_files: bool, vars: &HashMap<String, String>)
+             -> Result<Config, ConfigError> {
+        let mut data = String::new();
+        if let Err(e) = File::open(path).and_then(|mut file| file.read_to_string(&mut data)) {
+            return Err(ConfigError { problems: vec![format!("Can't read {}: {}", path.display(), e)] });
+        }
+        let mut raw: RawConfig = match decode(&data[..]) {
+            Ok(raw) => raw,
+            Err(e) => {
+                return Err(ConfigError { problems: vec![format!("Invalid {}: {}", path.display(), e)] });
+            }
+        };
+        raw.apply_env(vars);
+
+        let mut validator = Validator {
+            problems: Vec::new(),
+            check_files: check_files,
+        };
+        // Without listeners, RORI listens on ip:port with TLS
+        let listeners = match raw.listeners {
+            Some(ref listeners) => {
+                if listeners.len() == 0 {
+                    validator.problems.push(String::from("listeners is empty"));
+                }
+                listeners.iter()
+                    .enumerate()
+                    .filter_map(|(index, listener)| validator.listener(index, listener))
+                    .collect()
+            }
+            None => {
+                validator.address("ip", &raw.ip, "port", &raw.port)
+                    .map(|address| vec![Listener::Tls(address)])
+                    .unwrap_or(Vec::new())
+            }
+        };
+        let api_address = validator.address("api_ip", &raw.api_ip, "api_port", &raw.api_port);
+        // A certificate is only needed to listen with TLS
+        let tls = match raw.listeners {
+            Some(ref listeners) => {
+                listeners.iter().any(|listener| {
+                    let transport = listener.transport.clone().unwrap_or(String::from("tls"));
+                    transport == "tls" || transport == "wss"
+                })
+            }
+            None => true,
+        };
+        let mut file = |name: &str, value: &Option<String>| {
+            let value = if tls {
+                validator.required(name, value)
+            } else {
+                value.clone()
+            };
+            value.map(|value| validator.file(name, value))
+        };
+        let cert = file("cert", &raw.cert);
+        let key = file("key", &raw.key);
+        let client_ca = raw.client_ca.clone().map(|ca| validator.file("client_ca", ca));
+        let require_client_cert = match raw.client_auth {
+            None => true,
+            Some(ref mode) if mode == "required" => true,
+            Some(ref mode) if mode == "optional" => false,
+            Some(ref mode) => {
+                validator.problems
+                    .push(format!("client_auth: \"{}\" must be required or optional", mode));
+                true
+            }
+        };
+        let max_frame_size =
+            validator.positive("max_frame_size", &raw.max_frame_size, DEFAULT_MAX_FRAME_SIZE);
+        let max_clock_skew =
+            validator.parse("max_clock_skew", &raw.max_clock_skew, DEFAULT_MAX_CLOCK_SKEW);
+        let nonce_cache_size =
+            validator.positive("nonce_cache_size", &raw.nonce_cache_size, DEFAULT_NONCE_CACHE_SIZE);
+        let workers = validator.positive("workers", &raw.workers, DEFAULT_WORKERS);
+        let queue_size = validator.parse("queue_size", &raw.queue_size, DEFAULT_QUEUE_SIZE);
+        let overflow = match raw.overflow {
+            None => OverflowPolicy::Reject,
+            Some(ref policy) => {
+                OverflowPolicy::from_str(&*policy).unwrap_or_else(|| {
+                    validator.problems
+                        .push(format!("overflow: \"{}\" must be reject or block", policy));
+                    OverflowPolicy::Reject
+                })
+            }
+        };
+        let max_connections =
+            validator.positive("max_connections", &raw.max_connections, DEFAULT_MAX_CONNECTIONS);
+        let shutdown_timeout = validator.at_most("shutdown_timeout",
+                                                 &raw.shutdown_timeout,
+                                                 DEFAULT_SHUTDOWN_TIMEOUT,
+                                                 MAX_SHUTDOWN_TIMEOUT);
+        let endpoint_lease = validator.at_most("endpoint_lease",
+                                               &raw.endpoint_lease,
+                                               DEFAULT_ENDPOINT_LEASE,
+                                               MAX_ENDPOINT_LEASE);
+        let delivery_retries =
+            validator.parse("delivery_retries", &raw.delivery_retries, DEFAULT_DELIVERY_RETRIES);
+        let webhooks = raw.webhooks
+            .clone()
+            .unwrap_or(Vec::new())
+            .iter()
+            .enumerate()
+            .filter_map(|(index, webhook)| validator.webhook(index, webhook))
+            .collect();
+        let webhook_retries = validator.at_most("webhook_retries",
+                                                &raw.webhook_retries,
+                                                DEFAULT_WEBHOOK_RETRIES,
+                                                MAX_WEBHOOK_RETRIES);
+        let authorize = validator.authorize(&raw.authorize);
+
+        if validator.problems.len() != 0 {
+            return Err(ConfigError { problems: validator.problems });
+        }
+        Ok(Config {
+            listeners: listeners,
+            api_address: api_address.unwrap(),
+            cert: cert,
+            key: key,
+            client_ca: client_ca,
+            require_client_cert: require_client_cert,
+            max_frame_size: max_frame_size,
+            max_clock_skew: Duration::from_secs(max_clock_skew),
+            nonce_cache_size: nonce_cache_size,
+            workers: workers,
+            queue_size: queue_size,
+            overflow: overflow,
+            max_connections: max_connections,
+            shutdown_timeout: Duration::from_secs(shutdown_timeout),
+            endpoints_file: raw.endpoints_file.map(PathBuf::from),
+            endpoint_lease: Duration::from_secs(endpoint_lease),
+            delivery_retries: delivery_retries,
+            dead_letters_file: raw.dead_letters_file.map(PathBuf::from),
+            webhooks: webhooks,
+            webhook_retries: webhook_retries,
+            authorize: authorize,
+        })
+    }
+}
@@ -0,0 +1,406 @@
+use core::{ENDPOINTMANAGER, EVENTS, METRICS, SHUTDOWN};
+use core::auth_manager::{AuthManager, PeerIdentity};
+use core::endpoint_manager::{Endpoint, Pusher, endpoint_event, write_atomically};
+use core::events::Event;
+use openssl::ssl::{Ssl, SslContext, SslMethod};
+use rustc_serialize::json;
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/**
+ * Default number of retries before a message becomes a dead letter
+ */
+pub const DEFAULT_DELIVERY_RETRIES: u64 = 5;
+
+/**
+ * Delay before the first retry (in seconds). Doubled after each failure.
+ */
+const FIRST_RETRY_DELAY: u64 = 1;
+const MAX_RETRY_DELAY: u64 = 300;
+
+/**
+ * How long to wait for an endpoint to connect, read or write (in seconds)
+ */
+const DELIVERY_TIMEOUT: u64 = 10;
+
+/**
+ * How many messages can wait for an endpoint. Next messages are dead letters.
+ */
+pub const MAX_QUEUED_MESSAGES: usize = 1000;
+
+/**
+ * How many dead letters are kept. The oldest ones are dropped.
+ */
+pub const MAX_DEAD_LETTERS: usize = 1000;
+
+/**
+ * A message which couldn't be sent to an endpoint
+ */
+#[derive(Clone, PartialEq, Debug, RustcEncodable, RustcDecodable)]
+pub struct DeadLetter {
+    pub id: u64,
+    /// the id of the endpoint
+    pub endpoint: u64,
+    pub data: String,
+    pub attempts: u64,
+    /// why the last attempt failed
+    pub error: String,
+    /// when the message became a dead letter (UNIX time)
+    pub time: u64,
+}
+
+/**
+ * A message waiting to be sent
+ */
+#[derive(Clone)]
+struct Delivery {
+    data: String,
+    attempts: u64,
+}
+
+/**
+ * Send data to endpoints in background. Each endpoint has its own queue, sent in order by its
+ * own worker, so a slow endpoint doesn't block others. Failed messages are retried with an
+ * exponential backoff, then kept as dead letters.
+ */
+pub struct DeliveryQueue {
+    queues: Mutex<HashMap<u64, VecDeque<Delivery>>>,
+    dead_letters: Mutex<Vec<DeadLetter>>,
+    /// where dead letters are saved after each change
+    store: Mutex<Option<PathBuf>>,
+    retries: AtomicUsize,
+    /// delay before the first retry (in milliseconds)
+    retry_delay: AtomicUsize,
+    next_id: AtomicUsize,
+}
+
+impl DeliveryQueue {
+    pub fn new() -> DeliveryQueue {
+        DeliveryQueue {
+            queues: Mutex::new(HashMap::new()),
+            dead_letters: Mutex::new(Vec::new()),
+            store: Mutex::new(None),
+            retries: AtomicUsize::new(DEFAULT_DELIVERY_RETRIES as usize),
+            retry_delay: AtomicUsize::new(FIRST_RETRY_DELAY as usize * 1000),
+            next_id: AtomicUsize::new(0),
+        }
+    }
+
+    /**
+     * @param retries: how many times a failed message is sent again
+     * @param first_delay: delay before the first retry, doubled after each failure
+     */
+    pub fn set_retries(&self, retries: u64, first_delay: Duration) {
+        let first_delay = first_delay.as_secs() * 1000 + first_delay.subsec_millis() as u64;
+        self.retries.store(retries as usize, Ordering::SeqCst);
+        self.retry_delay.store(first_delay as usize, Ordering::SeqCst);
+    }
+
+    /**
+     * Restore the dead letters saved in a file, then save them in this file after each change
+     * @return the number of restored dead letters
+     */
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<usize> {
+        let path = path.as_ref();
+        let mut dead_letters = self.dead_letters.lock().unwrap();
+        if path.exists() {
+            let mut content = String::new();
+            File::open(path)?.read_to_string(&mut content)?;
+            let restored: Vec<DeadLetter> = json::decode(&*content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            dead_letters.extend(restored);
+            let next_id = dead_letters.iter().map(|letter| letter.id + 1).max().unwrap_or(0);
+            if next_id as usize > self.next_id.load(Ordering::SeqCst) {
+                self.next_id.store(next_id as usize, Ordering::SeqCst);
+            }
+        }
+        *self.store.lock().unwrap() = Some(path.to_path_buf());
+        Ok(dead_letters.len())
+    }
+
+    /**
+     * Queue data for an endpoint
+     * @return false if the queue of the endpoint is full (the data is a dead letter)
+     */
+    pub fn send(&'static self, endpoint: u64, data: String) -> bool {
+        {
+            let mut queues = self.queues.lock().unwrap();
+            let start_worker = !queues.contains_key(&endpoint);
+            let queue = queues.entry(endpoint).or_default();
+            if queue.len() < MAX_QUEUED_MESSAGES {
+                queue.push_back(Delivery {
+                    data: data,
+                    attempts: 0,
+                });
+                if start_worker {
+                    thread::spawn(move || self.work(endpoint));
+                }
+                return true;
+            }
+        }
+        warn!(target:"Delivery", "Too many messages for endpoint {}", endpoint);
+        self.give_up(endpoint, None, data, 0, String::from("too many queued messages"));
+        false
+    }
+
+    /**
+     * @return the number of messages waiting to be sent
+     */
+    pub fn pending(&self) -> usize {
+        self.queues.lock().unwrap().values().map(|queue| queue.len()).sum()
+    }
+
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.dead_letters.lock().unwrap().clone()
+    }
+
+    pub fn dead_letter(&self, id: u64) -> Option<DeadLetter> {
+        self.dead_letters.lock().unwrap().iter().find(|letter| letter.id == id).cloned()
+    }
+
+    /**
+     * Forget a dead letter
+     * @return the dead letter if it exists
+     */
+    pub fn remove_dead_letter(&self, id: u64) -> Option<DeadLetter> {
+        let removed = {
+            let mut dead_letters = self.dead_letters.lock().unwrap();
+            let index = dead_letters.iter().position(|letter| letter.id == id);
+            index.map(|index| dead_letters.remove(index))
+        };
+        if removed.is_some() {
+            self.persist();
+        }
+        removed
+    }
+
+    /**
+     * Queue a dead letter again for its endpoint
+     * @return if the dead letter exists
+     */
+    pub fn replay(&'static self, id: u64) -> bool {
+        match self.remove_dead_letter(id) {
+            Some(letter) => {
+                info!(target:"Delivery", "Replay dead letter {} for endpoint {}", id, letter.endpoint);
+                self.send(letter.endpoint, letter.data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /**
+     * Send the messages of an endpoint until its queue is empty
+     */
+    fn work(&'static self, endpoint_id: u64) {
+        loop {
+            let delivery = {
+                let mut queues = self.queues.lock().unwrap();
+                match queues.get(&endpoint_id).and_then(|queue| queue.front().cloned()) {
+                    Some(delivery) => delivery,
+                    None => {
+                        queues.remove(&endpoint_id);
+                        return;
+                    }
+                }
+            };
+            if SHUTDOWN.load(Ordering::SeqCst) {
+                self.stop(endpoint_id);
+                return;
+            }
+            // Don't keep the registry locked while sending
+            let target = ENDPOINTMANAGER.lock().unwrap().target(endpoint_id);
+            let (endpoint, pusher) = match target {
+                Some(target) => target,
+                None => {
+                    self.pop(endpoint_id);
+                    self.give_up(endpoint_id,
+                                 None,
+                                 delivery.data,
+                                 delivery.attempts,
+                                 String::from("unknown endpoint"));
+                    continue;
+                }
+            };
+            let transport = if pusher.is_some() { "websocket" } else { "tls" };
+            let result = match pusher {
+                Some(pusher) => push(&pusher, &*delivery.data),
+                None => write_tls(&endpoint, &*delivery.data),
+            };
+            METRICS.delivered(transport, result.is_ok());
+            let error = match result {
+                Ok(_) => {
+                    self.pop(endpoint_id);
+                    EVENTS.publish(|| {
+                        endpoint_event("delivered", &endpoint).with("transport", String::from(transport))
+                    });
+                    continue;
+                }
+                Err(error) => error,
+            };
+            let attempts = delivery.attempts + 1;
+            if attempts > self.retries.load(Ordering::SeqCst) as u64 {
+                error!(target:"Delivery", "Can't send data to endpoint {}: {}. Give up.",
+                       endpoint_id, error);
+                self.pop(endpoint_id);
+                self.give_up(endpoint_id, Some(&endpoint), delivery.data, attempts, error);
+                continue;
+            }
+            let first_delay = self.retry_delay.load(Ordering::SeqCst) as u64;
+            let delay = cmp::min(first_delay << cmp::min(attempts - 1, 16), MAX_RETRY_DELAY * 1000);
+            warn!(target:"Delivery", "Can't send data to endpoint {}: {}. Retry in {}ms",
+                  endpoint_id, error, delay);
+            if let Some(front) = self.queues
+                .lock()
+                .unwrap()
+                .get_mut(&endpoint_id)
+                .and_then(|queue| queue.front_mut()) {
+                front.attempts = attempts;
+            }
+            DeliveryQueue::sleep(Duration::from_millis(delay));
+        }
+    }
+
+    /**
+     * Wait before a retry, unless RORI stops
+     */
+    fn sleep(delay: Duration) {
+        let step = Duration::from_millis(100);
+        let mut waited = Duration::from_millis(0);
+        while waited < delay && !SHUTDOWN.load(Ordering::SeqCst) {
+            thread::sleep(cmp::min(step, delay - waited));
+            waited += step;
+        }
+    }
+
+    fn pop(&self, endpoint_id: u64) {
+        if let Some(queue) = self.queues.lock().unwrap().get_mut(&endpoint_id) {
+            queue.pop_front();
+        }
+    }
+
+    /**
+     * Keep the messages of an endpoint as dead letters when RORI stops
+     */
+    fn stop(&self, endpoint_id: u64) {
+        let queue = self.queues.lock().unwrap().remove(&endpoint_id).unwrap_or_default();
+        for delivery in queue {
+            self.give_up(endpoint_id,
+                         None,
+                         delivery.data,
+                         delivery.attempts,
+                         String::from("RORI stopped"));
+        }
+    }
+
+    /**
+     * Keep a message as a dead letter
+     * @param endpoint: the endpoint, if known, for the delivery_failed event
+     */
+    fn give_up(&self,
+               endpoint_id: u64,
+               endpoint: Option<&Endpoint>,
+               data: String,
+               attempts: u64,
+               error: String) {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0);
+        let letter = DeadLetter {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst) as u64,
+            endpoint: endpoint_id,
+            data: data,
+            attempts: attempts,
+            error: error,
+            time: time,
+        };
+        EVENTS.publish(|| {
+            let event = match endpoint {
+                Some(endpoint) => endpoint_event("delivery_failed", endpoint),
+                None => Event::new("delivery_failed").with("id", endpoint_id),
+            };
+            event.with("dead_letter", letter.id)
+                .with("attempts", letter.attempts)
+                .with("error", letter.error.clone())
+        });
+        {
+            let mut dead_letters = self.dead_letters.lock().unwrap();
+            dead_letters.push(letter);
+            if dead_letters.len() > MAX_DEAD_LETTERS {
+                let dropped = dead_letters.remove(0);
+                warn!(target:"Delivery", "Too many dead letters, drop {}", dropped.id);
+            }
+        }
+        self.persist();
+    }
+
+    /**
+     * Save dead letters in their store, if opened
+     */
+    fn persist(&self) {
+        let store = self.store.lock().unwrap().clone();
+        if let Some(store) = store {
+            let encoded = json::encode(&*self.dead_letters.lock().unwrap())
+                .unwrap_or(String::from("[]"));
+            if let Err(e) = write_atomically(&store, &*encoded) {
+                error!(target:"Delivery", "Can't save dead letters in {}: {}", store.display(), e);
+            }
+        }
+    }
+}
+
+/**
+ * Send data over the WebSocket connection of an endpoint
+ */
+fn push(pusher: &Pusher, data: &str) -> Result<(), String> {
+    pusher.sender.send(String::from(data)).map_err(|_| String::from("connection closed"))
+}
+
+/**
+ * Connect to the first address which answers in time
+ * @param address host:port of the endpoint
+ * @param timeout how long to wait for each address
+ * @return the stream, or the last error
+ */
+fn connect(address: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut error = io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to");
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+/**
+ * Connect to an endpoint with TLS, as it expects, and write data
+ */
+fn write_tls(endpoint: &Endpoint, data: &str) -> Result<(), String> {
+    let context = SslContext::builder(SslMethod::tls()).map_err(|e| e.to_string())?.build();
+    let mut ssl = Ssl::new(&context).map_err(|e| e.to_string())?;
+    if let Some(ref server_name) = endpoint.tls.server_name {
+        ssl.set_hostname(&*server_name).map_err(|e| e.to_string())?;
+    }
+    let timeout = Duration::from_secs(DELIVERY_TIMEOUT);
+    let inner = connect(&endpoint.address, timeout).map_err(|e| e.to_string())?;
+    inner.set_read_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+    inner.set_write_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+    let mut stream = ssl.connect(inner).map_err(|e| e.to_string())?;
+    if let Some(ref expected) = endpoint.tls.fingerprint {
+        let fingerprint = stream.ssl()
+            .peer_certificate()
+            .map(|cert| PeerIdentity::from_certificate(&cert).fingerprint)
+            .unwrap_or_default();
+        if fingerprint != AuthManager::normalize_fingerprint(expected) {
+            return Err(format!("unexpected certificate (fingerprint {})", fingerprint));
+        }
+    }
+    stream.write_all(data.as_bytes()).map_err(|e| e.to_string())
+}
@@ -1,21 +1,284 @@
-use openssl::ssl::{SslContext, SslMethod, Ssl};
+use core::{DELIVERIES, EVENTS};
+use core::auth_manager::AuthManager;
+use core::events::Event;
+use core::framing::PROTOCOL_VERSION;
 use rori_utils::data::RoriData;
-use std::net::TcpStream;
-use std::io::Write;
+use rustc_serialize::json::{self, Json, Object};
+use std::fs::{self, File};
+use std::io;
+use std::io::{Read, Write};
+use std::mem;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
 
-#[derive(Clone, PartialEq, Eq, RustcEncodable)]
+/**
+ * Default lease of an endpoint (in seconds). Endpoints which don't send a heartbeat or register
+ * again during their lease are offline, then removed after another lease.
+ */
+pub const DEFAULT_ENDPOINT_LEASE: u64 = 300;
+/**
+ * Maximum lease allowed in the configuration (one week)
+ */
+pub const MAX_ENDPOINT_LEASE: u64 = 604800;
+
+/**
+ * What an endpoint expects from RORI when it connects to it with TLS
+ */
+#[derive(Clone, Default, PartialEq, Debug, RustcEncodable)]
+pub struct TlsExpectations {
+    /// the name sent with SNI
+    pub server_name: Option<String>,
+    /// the SHA-256 fingerprint of the certificate of the endpoint, to pin it
+    pub fingerprint: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, RustcEncodable)]
 pub struct Endpoint {
     pub name: String,
     pub id: u64,
     pub owner: String,
     pub address: String,
     pub compatible_data: Vec<String>,
+    pub display_name: Option<String>,
+    pub tags: Vec<String>,
+    /// 0 for endpoints registered with the legacy format
+    pub protocol_version: u32,
+    pub tls: TlsExpectations,
+    /// anything else the endpoint wants modules to know (a JSON object)
+    pub capabilities: Json,
+}
+
+impl Endpoint {
+    /**
+     * Read an endpoint saved by EndpointManager::save. Fields added after the first version are
+     * optional.
+     */
+    fn from_json(json: &Json) -> Result<Endpoint, String> {
+        let registration = Registration::from_object(json, "compatible_data")?;
+        Ok(Endpoint {
+            name: field(json, "name")?.unwrap_or_default(),
+            id: json.find("id").and_then(|id| id.as_u64()).ok_or("id is missing")?,
+            owner: field(json, "owner")?.unwrap_or_default(),
+            address: registration.address,
+            compatible_data: registration.datatypes,
+            display_name: registration.display_name,
+            tags: registration.tags,
+            protocol_version: json.find("protocol_version")
+                .and_then(|version| version.as_u64())
+                .unwrap_or(0) as u32,
+            tls: registration.tls,
+            capabilities: registration.capabilities,
+        })
+    }
+}
+
+/**
+ * The content of a register command. Either JSON:
+ * {"address":"127.0.0.1:1415","datatypes":["text"],"display_name":"Desktop","tags":["home"],
+ *  "protocol_version":1,"tls":{"server_name":"rori","fingerprint":"..."},"capabilities":{}}
+ * or the legacy format: ip:port|type1|type2...
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct Registration {
+    pub address: String,
+    pub datatypes: Vec<String>,
+    pub display_name: Option<String>,
+    pub tags: Vec<String>,
+    pub protocol_version: u32,
+    pub tls: TlsExpectations,
+    pub capabilities: Json,
+}
+
+impl Registration {
+    /**
+     * @param content: the content of the register command
+     * @return the registration, or why it is invalid
+     */
+    pub fn parse(content: &str) -> Result<Registration, String> {
+        if !content.trim_start().starts_with('{') {
+            let mut parts = content.split('|');
+            return Ok(Registration {
+                address: String::from(parts.next().unwrap_or("")),
+                datatypes: parts.filter(|part| part.len() > 0).map(String::from).collect(),
+                display_name: None,
+                tags: Vec::new(),
+                protocol_version: 0,
+                tls: TlsExpectations::default(),
+                capabilities: Json::Object(Object::new()),
+            });
+        }
+        let json = Json::from_str(content).map_err(|e| e.to_string())?;
+        let mut registration = Registration::from_object(&json, "datatypes")?;
+        registration.protocol_version = match json.find("protocol_version") {
+            Some(version) => {
+                match version.as_u64() {
+                    Some(version) if version >= 1 && version <= PROTOCOL_VERSION as u64 => {
+                        version as u32
+                    }
+                    _ => return Err(format!("protocol_version: {} is not supported", version)),
+                }
+            }
+            None => PROTOCOL_VERSION,
+        };
+        Ok(registration)
+    }
+
+    /**
+     * Read the fields common to registrations and saved endpoints
+     * @param datatypes: the key of the datatypes
+     */
+    fn from_object(json: &Json, datatypes: &str) -> Result<Registration, String> {
+        if !json.is_object() {
+            return Err(String::from("not a JSON object"));
+        }
+        let tls = match json.find("tls") {
+            Some(tls) if tls.is_object() => {
+                TlsExpectations {
+                    server_name: field(tls, "server_name")?,
+                    fingerprint: field(tls, "fingerprint")?,
+                }
+            }
+            Some(&Json::Null) | None => TlsExpectations::default(),
+            Some(_) => return Err(String::from("tls must be an object")),
+        };
+        let capabilities = match json.find("capabilities") {
+            Some(capabilities) if capabilities.is_object() => capabilities.clone(),
+            Some(&Json::Null) | None => Json::Object(Object::new()),
+            Some(_) => return Err(String::from("capabilities must be an object")),
+        };
+        Ok(Registration {
+            address: field(json, "address")?.unwrap_or_default(),
+            datatypes: list(json, datatypes)?,
+            display_name: field(json, "display_name")?,
+            tags: list(json, "tags")?,
+            protocol_version: 0,
+            tls: tls,
+            capabilities: capabilities,
+        })
+    }
+
+    /**
+     * @return why the registration can't be used, if invalid
+     */
+    pub fn validate(&self) -> Result<(), String> {
+        // host:port, [ipv6]:port
+        let port = self.address.rsplit(':').next().unwrap_or("");
+        let host = self.address[..self.address.len() - port.len()].trim_end_matches(':');
+        if host.len() == 0 || port.parse::<u16>().is_err() {
+            return Err(format!("address: \"{}\" is not host:port", self.address));
+        }
+        if self.datatypes.len() == 0 {
+            return Err(String::from("datatypes is missing"));
+        }
+        if let Some(ref fingerprint) = self.tls.fingerprint {
+            let fingerprint = AuthManager::normalize_fingerprint(fingerprint);
+            if fingerprint.len() != 64 || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(String::from("tls.fingerprint must be a SHA-256 fingerprint"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+ * @return the string at key (None if missing or null), or an error if it isn't a string
+ */
+fn field(json: &Json, key: &str) -> Result<Option<String>, String> {
+    match json.find(key) {
+        Some(&Json::String(ref value)) => Ok(Some(value.clone())),
+        Some(&Json::Null) | None => Ok(None),
+        Some(_) => Err(format!("{} must be a string", key)),
+    }
+}
+
+/**
+ * @return the strings at key (empty if missing or null), or an error if it isn't a list of strings
+ */
+fn list(json: &Json, key: &str) -> Result<Vec<String>, String> {
+    match json.find(key) {
+        Some(&Json::Array(ref values)) => {
+            values.iter()
+                .map(|value| value.as_string().map(String::from))
+                .collect::<Option<Vec<String>>>()
+                .ok_or_else(|| format!("{} must be a list of strings", key))
+        }
+        Some(&Json::Null) | None => Ok(Vec::new()),
+        Some(_) => Err(format!("{} must be a list of strings", key)),
+    }
+}
+
+/**
+ * Send data to an endpoint over the connection it registered with (for WebSocket clients)
+ */
+#[derive(Clone)]
+pub struct Pusher {
+    /// the connection of the endpoint
+    pub connection: usize,
+    pub sender: Sender<String>,
+}
+
+/**
+ * Which endpoints to get, depending on their lease
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Liveness {
+    Any,
+    Online,
+    Offline,
+}
+
+impl Liveness {
+    pub fn from_str(liveness: &str) -> Option<Liveness> {
+        match liveness {
+            "any" => Some(Liveness::Any),
+            "online" => Some(Liveness::Online),
+            "offline" => Some(Liveness::Offline),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * When an endpoint was last seen. Endpoints pushed over a WebSocket connection have no lease:
+ * they are removed when the connection is closed.
+ */
+#[derive(Clone)]
+struct Lease {
+    last_seen: Instant,
+    online: bool,
+}
+
+impl Lease {
+    fn new() -> Lease {
+        Lease {
+            last_seen: Instant::now(),
+            online: true,
+        }
+    }
+}
+
+/**
+ * The registry as written on disk
+ */
+#[derive(RustcEncodable)]
+struct Snapshot {
+    /// the id of the next endpoint. Ids are never reused.
+    next_id: u64,
+    endpoints: Vec<Endpoint>,
 }
 
 #[derive(Clone)]
 pub struct EndpointManager {
     endpoints: Vec<Endpoint>,
     id: u64,
+    pushers: HashMap<u64, Pusher>,
+    leases: HashMap<u64, Lease>,
+    /// how long an endpoint stays online without heartbeat (never expires if 0)
+    lease: Duration,
+    /// where the registry is saved after each change
+    store: Option<PathBuf>,
 }
 
 /**
@@ -26,55 +289,324 @@ impl EndpointManager {
         EndpointManager {
             endpoints: Vec::new(),
             id: 0,
+            pushers: HashMap::new(),
+            leases: HashMap::new(),
+            lease: Duration::from_secs(DEFAULT_ENDPOINT_LEASE),
+            store: None,
         }
     }
 
     /**
-     * Save an endpoint if it's not a duplicate
+     * @param lease: how long endpoints stay online without heartbeat. 0 to keep them forever.
      */
-    pub fn register_endpoint(&mut self, data: RoriData) {
-        // if we receive a register command
-        if data.datatype == "register" {
-            // content = ip:port|type1|type2...
-            let mut collected_part: Vec<&str> = data.content.split('|').collect();
-            let mut address = String::from("");
-            let mut content_part: Vec<String> = Vec::new();
-            while collected_part.len() > 0 {
-                let collected = String::from(collected_part.pop().unwrap_or(""));
-                if collected_part.len() == 0 {
-                    address = collected;
-                } else {
-                    content_part.push(collected);
+    pub fn set_lease(&mut self, lease: Duration) {
+        self.lease = lease;
+    }
+
+    /**
+     * Restore the endpoints saved in a file, then save them in this file after each change
+     * @param path: where endpoints are saved. Nothing is restored if it doesn't exist yet.
+     * @return the number of restored endpoints, or an error if one of their ids is already used
+     */
+    pub fn open<P: AsRef<Path>>(&mut self, path: P) -> io::Result<usize> {
+        let path = path.as_ref();
+        let mut restored = 0;
+        if path.exists() {
+            let mut content = String::new();
+            File::open(path)?.read_to_string(&mut content)?;
+            let snapshot = EndpointManager::decode_snapshot(&*content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            // Ids must stay the same, so an id already used can't be given to a restored endpoint
+            let mut ids: HashSet<u64> = self.endpoints.iter().map(|endpoint| endpoint.id).collect();
+            for endpoint in &snapshot.endpoints {
+                if !ids.insert(endpoint.id) {
+                    return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                              format!("endpoint #{} is already registered",
+                                                      endpoint.id)));
                 }
             }
-            let endpoint = Endpoint {
-                name: data.client,
-                id: self.id,
-                owner: data.author,
-                address: address,
-                compatible_data: content_part,
-            };
-            // Avoid duplicates
-            if !self.endpoint_already_exists(&endpoint) {
+            restored = snapshot.endpoints.len();
+            // Endpoints couldn't send heartbeats while RORI was stopped, so they get a new lease
+            for endpoint in snapshot.endpoints {
+                self.leases.insert(endpoint.id, Lease::new());
                 self.endpoints.push(endpoint);
-                self.id += 1;
+            }
+            let max_id = self.endpoints.iter().map(|endpoint| endpoint.id + 1).max().unwrap_or(0);
+            self.id = *[self.id, snapshot.next_id, max_id].iter().max().unwrap_or(&0);
+        }
+        self.store = Some(path.to_path_buf());
+        Ok(restored)
+    }
+
+    /**
+     * Files written before ids were saved only contain the list of endpoints
+     */
+    fn decode_snapshot(content: &str) -> Result<Snapshot, String> {
+        let json = Json::from_str(content).map_err(|e| e.to_string())?;
+        let (next_id, endpoints) = match json {
+            Json::Array(ref endpoints) => (0, endpoints),
+            Json::Object(_) => {
+                let next_id = json.find("next_id")
+                    .and_then(|id| id.as_u64())
+                    .ok_or("next_id is missing")?;
+                match json.find("endpoints") {
+                    Some(&Json::Array(ref endpoints)) => (next_id, endpoints),
+                    _ => return Err(String::from("endpoints is missing")),
+                }
+            }
+            _ => return Err(String::from("not a list of endpoints")),
+        };
+        Ok(Snapshot {
+            next_id: next_id,
+            endpoints: endpoints.iter()
+                .map(Endpoint::from_json)
+                .collect::<Result<Vec<Endpoint>, String>>()?,
+        })
+    }
+
+    /**
+     * Save the registry in its store, if opened
+     */
+    fn persist(&self) {
+        if let Some(ref store) = self.store {
+            if let Err(e) = self.save(store) {
+                error!(target:"EndpointManager", "Can't save endpoints in {}: {}", store.display(), e);
             }
         }
     }
 
+    /**
+     * Save an endpoint if it's not a duplicate
+     * @return the id of the endpoint (the existing one for a duplicate), None if the registration
+     * is invalid
+     */
+    pub fn register_endpoint(&mut self, data: RoriData) -> Option<u64> {
+        // if we receive a register command
+        if data.datatype != "register" {
+            return None;
+        }
+        let registration = Registration::parse(&*data.content)
+            .and_then(|registration| registration.validate().map(|_| registration));
+        match registration {
+            Ok(registration) => Some(self.add(data, registration, None)),
+            Err(e) => {
+                error!(target:"EndpointManager", "Invalid registration from {}: {}", data.client, e);
+                None
+            }
+        }
+    }
+
+    /**
+     * Save an endpoint unless it's a duplicate
+     * @param pusher: where data is sent if the endpoint receives it over its connection
+     * @return the id of the endpoint
+     */
+    fn add(&mut self, data: RoriData, registration: Registration, pusher: Option<Pusher>) -> u64 {
+        let endpoint = Endpoint {
+            name: data.client,
+            id: self.id,
+            owner: data.author,
+            address: registration.address,
+            compatible_data: registration.datatypes,
+            display_name: registration.display_name,
+            tags: registration.tags,
+            protocol_version: registration.protocol_version,
+            tls: registration.tls,
+            capabilities: registration.capabilities,
+        };
+        // Avoid duplicates. Registering again renews the lease and updates the metadata.
+        if let Some(id) = self.find_duplicate(&endpoint) {
+            if let Some(pusher) = pusher {
+                self.leases.remove(&id);
+                self.pushers.insert(id, pusher);
+            }
+            self.renew(id);
+            let endpoint = Endpoint { id: id, ..endpoint };
+            let index = self.get_endpoint_index(id) as usize;
+            if self.endpoints[index] != endpoint {
+                self.endpoints[index] = endpoint;
+                self.persist();
+            }
+            return id;
+        }
+        EVENTS.publish(|| endpoint_event("endpoint_registered", &endpoint));
+        match pusher {
+            // Not saved: the connection is lost when RORI restarts. Only the next id is saved.
+            Some(pusher) => {
+                self.pushers.insert(endpoint.id, pusher);
+            }
+            None => {
+                self.leases.insert(endpoint.id, Lease::new());
+            }
+        }
+        self.endpoints.push(endpoint);
+        self.id += 1;
+        self.persist();
+        self.id - 1
+    }
+
+    /**
+     * Save an endpoint which receives data over the connection it registered with
+     * @param data: the register command
+     * @param pusher: where data for this endpoint is sent
+     * @return the id of the endpoint, None if the registration is invalid
+     */
+    pub fn register_pushed_endpoint(&mut self, data: RoriData, pusher: Pusher) -> Option<u64> {
+        let mut registration = match Registration::parse(&*data.content) {
+            Ok(registration) => registration,
+            Err(e) => {
+                error!(target:"EndpointManager", "Invalid registration from {}: {}", data.client, e);
+                return None;
+            }
+        };
+        // The address given by the client is useless, the connection is used
+        registration.address = format!("websocket:{}", pusher.connection);
+        if let Err(e) = registration.validate() {
+            error!(target:"EndpointManager", "Invalid registration from {}: {}", data.client, e);
+            return None;
+        }
+        Some(self.add(data, registration, Some(pusher)))
+    }
+
+    /**
+     * Remove endpoints registered with a connection, when it is closed
+     * @param connection: the closed connection
+     */
+    pub fn remove_pushed_endpoints(&mut self, connection: usize) {
+        let ids: Vec<u64> = self.pushers
+            .iter()
+            .filter(|&(_, pusher)| pusher.connection == connection)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            self.remove_endpoint(id);
+        }
+    }
+
+    /**
+     * Renew the lease of the endpoints of a client
+     * @param data: the heartbeat. Its content is the address of the endpoint, or empty for all
+     * endpoints of the client and author.
+     * @return the number of renewed endpoints
+     */
+    pub fn heartbeat(&mut self, data: &RoriData) -> usize {
+        let ids = self.own_endpoints(data);
+        for id in &ids {
+            self.renew(*id);
+        }
+        ids.len()
+    }
+
+    /**
+     * Remove the endpoints of a client
+     * @param data: the unregister command. Its content is the address of the endpoint, or empty
+     * for all endpoints of the client and author.
+     * @return the number of removed endpoints
+     */
+    pub fn unregister(&mut self, data: &RoriData) -> usize {
+        let ids = self.own_endpoints(data);
+        for id in &ids {
+            self.remove_endpoint(*id);
+        }
+        ids.len()
+    }
+
+    /**
+     * @return the ids of the endpoints registered by the sender of data, with the address given
+     * in the content (or all if empty)
+     */
+    fn own_endpoints(&self, data: &RoriData) -> Vec<u64> {
+        let address = data.content.split('|').next().unwrap_or("");
+        self.endpoints
+            .iter()
+            .filter(|endpoint| {
+                endpoint.name.to_lowercase() == data.client.to_lowercase() &&
+                endpoint.owner == data.author &&
+                (address.len() == 0 || endpoint.address == address)
+            })
+            .map(|endpoint| endpoint.id)
+            .collect()
+    }
+
+    fn renew(&mut self, id: u64) {
+        let back_online = match self.leases.get_mut(&id) {
+            Some(lease) => {
+                lease.last_seen = Instant::now();
+                !mem::replace(&mut lease.online, true)
+            }
+            None => false,
+        };
+        if back_online {
+            if let Some(endpoint) = self.get_endpoint(id) {
+                info!(target:"EndpointManager", "Endpoint {} is online", id);
+                EVENTS.publish(|| endpoint_event("endpoint_online", &endpoint));
+            }
+        }
+    }
+
+    /**
+     * Mark endpoints without heartbeat during their lease offline, and remove endpoints offline
+     * for another lease
+     */
+    pub fn expire(&mut self) {
+        if self.lease == Duration::from_secs(0) {
+            return;
+        }
+        let mut expired = Vec::new();
+        let mut evicted = Vec::new();
+        // None if the lease is too long to be reached twice
+        let eviction = self.lease.checked_mul(2);
+        for (id, lease) in &mut self.leases {
+            let elapsed = lease.last_seen.elapsed();
+            if eviction.map_or(false, |eviction| elapsed > eviction) {
+                evicted.push(*id);
+            } else if elapsed > self.lease && lease.online {
+                lease.online = false;
+                expired.push(*id);
+            }
+        }
+        for id in expired {
+            if let Some(endpoint) = self.get_endpoint(id) {
+                warn!(target:"EndpointManager", "Endpoint {} is offline", id);
+                EVENTS.publish(|| endpoint_event("endpoint_offline", &endpoint));
+            }
+        }
+        for id in evicted {
+            warn!(target:"EndpointManager", "Endpoint {} expired", id);
+            self.remove_endpoint(id);
+        }
+    }
+
+    /**
+     * @return if the lease of an endpoint is not expired (always true for WebSocket endpoints)
+     */
+    pub fn is_online(&self, id: u64) -> bool {
+        self.leases.get(&id).map(|lease| lease.online).unwrap_or(true)
+    }
+
     /**
      * Get if an endpoint already exists
      * @return if we find a endpoint
      */
     pub fn endpoint_already_exists(&mut self, endpoint: &Endpoint) -> bool {
-        for elem in self.endpoints.clone() {
-            if endpoint.name == elem.name && endpoint.owner == elem.owner &&
-               elem.address == endpoint.address &&
-               elem.compatible_data == endpoint.compatible_data {
-                return true;
+        self.find_duplicate(endpoint).is_some()
+    }
+
+    fn find_duplicate(&self, endpoint: &Endpoint) -> Option<u64> {
+        // Old versions saved datatypes in reverse order
+        let sorted = |datatypes: &[String]| {
+            let mut datatypes = datatypes.to_vec();
+            datatypes.sort();
+            datatypes
+        };
+        for elem in &self.endpoints {
+            if endpoint.name.to_lowercase() == elem.name.to_lowercase() &&
+               endpoint.owner == elem.owner && elem.address == endpoint.address &&
+               sorted(&elem.compatible_data) == sorted(&endpoint.compatible_data) {
+                return Some(elem.id);
             }
         }
-        return false;
+        None
     }
 
     /**
@@ -84,7 +616,11 @@ impl EndpointManager {
     pub fn remove_endpoint(&mut self, id_to_rm: u64) -> bool {
         let index_to_remove = self.get_endpoint_index(id_to_rm);
         if index_to_remove >= 0 {
-            self.endpoints.remove(index_to_remove as usize);
+            let endpoint = self.endpoints.remove(index_to_remove as usize);
+            EVENTS.publish(|| endpoint_event("endpoint_removed", &endpoint));
+            self.pushers.remove(&id_to_rm);
+            self.leases.remove(&id_to_rm);
+            self.persist();
             return true;
         }
         return false;
@@ -105,16 +641,40 @@ impl EndpointManager {
         -1
     }
 
+    /**
+     * @return the number of registered endpoints
+     */
+    pub fn count(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    /**
+     * @return the endpoint with this id, if registered
+     */
+    pub fn get_endpoint(&self, id: u64) -> Option<Endpoint> {
+        self.endpoints.iter().find(|endpoint| endpoint.id == id).cloned()
+    }
+
     /**
      * Get endpoints
+     * @param liveness: only online or offline endpoints, or both
      */
-    pub fn get_endpoint_for_type(&self, datatype: String, owner: String) -> Vec<Endpoint> {
+    pub fn get_endpoint_for_type(&self,
+                                 datatype: String,
+                                 owner: String,
+                                 liveness: Liveness)
+                                 -> Vec<Endpoint> {
         let mut result: Vec<Endpoint> = Vec::new();
         let endpoints = self.endpoints.clone();
         for endpoint in endpoints {
             if endpoint.compatible_data.contains(&datatype) {
                 if owner.len() == 0 || owner == endpoint.owner || endpoint.owner == "*" {
-                    result.push(endpoint);
+                    let online = self.is_online(endpoint.id);
+                    match liveness {
+                        Liveness::Online if !online => continue,
+                        Liveness::Offline if online => continue,
+                        _ => result.push(endpoint),
+                    }
                 }
             }
         }
@@ -122,21 +682,66 @@ impl EndpointManager {
     }
 
     /**
-     * send a data to the endpoint with the given id
+     * send a data to the endpoint with the given id. The data is queued and sent in background,
+     * see DeliveryQueue.
      */
     pub fn send_to_endpoint(&self, id: u64, data: &String) {
-        let endpoints = self.endpoints.clone();
-        for endpoint in endpoints {
-            if endpoint.id == id {
-                let context = SslContext::builder(SslMethod::tls()).unwrap();
-                let ssl = context.build();
-                let inner = TcpStream::connect(&*endpoint.address).unwrap();
-                if let Ok(mut stream) = Ssl::new(&ssl).unwrap().connect(inner) {
-                    let _ = stream.write(data.to_string().as_bytes());
-                } else {
-                    error!(target:"RoriClient", "Couldn't connect to RORI at address {}", &*endpoint.address);
-                }
-            }
-        }
+        DELIVERIES.send(id, data.clone());
+    }
+
+    /**
+     * @return the endpoint with this id and its connection if it is a WebSocket endpoint
+     */
+    pub fn target(&self, id: u64) -> Option<(Endpoint, Option<Pusher>)> {
+        self.get_endpoint(id).map(|endpoint| (endpoint, self.pushers.get(&id).cloned()))
+    }
+
+    /**
+     * Write the registered endpoints and the next id in a file. The file is replaced atomically,
+     * so a crash never leaves a partial registry. Endpoints of WebSocket connections are not saved.
+     * @param path: where to save endpoints
+     */
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let snapshot = Snapshot {
+            next_id: self.id,
+            endpoints: self.endpoints
+                .iter()
+                .filter(|endpoint| !self.pushers.contains_key(&endpoint.id))
+                .cloned()
+                .collect(),
+        };
+        let encoded = json::encode(&snapshot)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        write_atomically(path, &*encoded)
+    }
+}
+
+/**
+ * Replace a file with content. The content is written in a temporary file renamed at the end,
+ * so the file is never partially written.
+ */
+pub fn write_atomically<P: AsRef<Path>>(path: P, content: &str) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut tmp = path.as_os_str().to_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
     }
+    fs::rename(&tmp, path)
+}
+
+/**
+ * An event about an endpoint: its client and owner are set
+ */
+pub fn endpoint_event(kind: &'static str, endpoint: &Endpoint) -> Event {
+    let mut event = Event::new(kind)
+        .with("id", endpoint.id)
+        .with("address", endpoint.address.clone())
+        .with("datatypes", endpoint.compatible_data.clone());
+    event.client = Some(endpoint.name.clone());
+    event.author = Some(endpoint.owner.clone());
+    event
 }
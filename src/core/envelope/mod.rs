@@ -0,0 +1,154 @@
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+use rustc_serialize::hex::{FromHex, ToHex};
+use rustc_serialize::json::{self, decode};
+use std::collections::{HashSet, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * Default accepted difference between the clock of a client and ours (in seconds)
+ */
+pub const DEFAULT_MAX_CLOCK_SKEW: u64 = 300;
+/**
+ * Default number of nonces remembered
+ */
+pub const DEFAULT_NONCE_CACHE_SIZE: usize = 10000;
+
+/**
+ * A RoriData signed by a client. The signature is an HMAC-SHA256 of
+ * client\ntimestamp\nnonce\npayload keyed by the signing_key of the client.
+ */
+#[derive(Clone, RustcDecodable, RustcEncodable, PartialEq, Debug)]
+pub struct Envelope {
+    pub client: String,
+    pub timestamp: u64,
+    pub nonce: String,
+    /// The RoriData (JSON)
+    pub payload: String,
+    /// Hex encoded HMAC
+    pub signature: String,
+}
+
+impl Envelope {
+    /**
+     * @param client: the name of the client
+     * @param nonce: an unique string
+     * @param payload: the RoriData to send
+     * @return an unsigned envelope for the current time
+     */
+    pub fn new(client: &str, nonce: &str, payload: &str) -> Envelope {
+        Envelope {
+            client: String::from(client),
+            timestamp: now(),
+            nonce: String::from(nonce),
+            payload: String::from(payload),
+            signature: String::from(""),
+        }
+    }
+
+    /**
+     * Sign the envelope
+     * @param key: the signing key of the client
+     */
+    pub fn sign(&mut self, key: &str) {
+        self.signature = self.compute_signature(key).to_hex();
+    }
+
+    /**
+     * @param data: a frame body
+     * @return the envelope if the body is a signed envelope
+     */
+    pub fn from_json(data: &str) -> Option<Envelope> {
+        decode(data).ok()
+    }
+
+    pub fn to_json(&self) -> String {
+        json::encode(self).unwrap_or(String::from("{}"))
+    }
+
+    /**
+     * @param key: the signing key of the client
+     * @return if the signature is correct
+     */
+    pub fn verify(&self, key: &str) -> bool {
+        match self.signature.from_hex() {
+            Ok(signature) => fixed_time_eq(&*self.compute_signature(key), &*signature),
+            Err(_) => false,
+        }
+    }
+
+    fn compute_signature(&self, key: &str) -> Vec<u8> {
+        let mut hmac = Hmac::new(Sha256::new(), key.as_bytes());
+        let message = format!("{}\n{}\n{}\n{}",
+                              self.client,
+                              self.timestamp,
+                              self.nonce,
+                              self.payload);
+        hmac.input(message.as_bytes());
+        hmac.result().code().to_vec()
+    }
+}
+
+/**
+ * Remember recent nonces to reject replayed envelopes
+ */
+pub struct NonceCache {
+    capacity: usize,
+    nonces: HashSet<String>,
+    order: VecDeque<(String, u64)>,
+}
+
+impl NonceCache {
+    pub fn new(capacity: usize) -> NonceCache {
+        NonceCache {
+            capacity: capacity,
+            nonces: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /**
+     * Remember a nonce
+     * @param nonce
+     * @param timestamp: when the nonce was used
+     * @param oldest: nonces used before this timestamp are forgotten
+     * @return false if the nonce was already used, or if the cache is full of nonces which can't
+     * be forgotten yet
+     */
+    pub fn insert(&mut self, nonce: &str, timestamp: u64, oldest: u64) -> bool {
+        while self.order.front().map(|&(_, ts)| ts < oldest).unwrap_or(false) {
+            if let Some((old, _)) = self.order.pop_front() {
+                self.nonces.remove(&old);
+            }
+        }
+        if self.nonces.contains(nonce) {
+            return false;
+        }
+        if self.order.len() >= self.capacity {
+            // Nonces are not always received in order of their timestamp
+            let nonces = &mut self.nonces;
+            self.order.retain(|&(ref old, ts)| ts >= oldest || !nonces.remove(old));
+        }
+        if self.order.len() >= self.capacity {
+            // Forgetting a nonce still in the clock skew window would allow to replay it
+            warn!(target:"NonceCache", "Nonce cache is full, reject the nonce");
+            return false;
+        }
+        self.nonces.insert(String::from(nonce));
+        self.order.push_back((String::from(nonce), timestamp));
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+}
+
+/**
+ * @return the current unix timestamp
+ */
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
@@ -0,0 +1,205 @@
+use core::METRICS;
+use rori_utils::data::RoriData;
+use rustc_serialize::json::{Json, Object, ToJson};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * How many events can wait for a slow subscriber. Next events are dropped for this subscriber.
+ */
+const SUBSCRIBER_QUEUE_SIZE: usize = 256;
+
+/**
+ * Maximum number of subscribers at the same time (event streams and webhooks)
+ */
+pub const MAX_SUBSCRIBERS: usize = 16;
+
+/**
+ * Something which happened in RORI: a message received, a module executed, an endpoint
+ * registered...
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct Event {
+    /// what happened, like message_received or module_finished
+    pub kind: &'static str,
+    pub client: Option<String>,
+    pub author: Option<String>,
+    pub datatype: Option<String>,
+    /// other fields of the event
+    pub details: Object,
+}
+
+impl Event {
+    pub fn new(kind: &'static str) -> Event {
+        Event {
+            kind: kind,
+            client: None,
+            author: None,
+            datatype: None,
+            details: Object::new(),
+        }
+    }
+
+    /**
+     * An event about a RoriData: its client, author and datatype are set
+     */
+    pub fn about(kind: &'static str, data: &RoriData) -> Event {
+        let mut event = Event::new(kind);
+        event.client = Some(data.client.clone());
+        event.author = Some(data.author.clone());
+        event.datatype = Some(data.datatype.clone());
+        event
+    }
+
+    pub fn with<T: ToJson>(mut self, key: &str, value: T) -> Event {
+        self.details.insert(String::from(key), value.to_json());
+        self
+    }
+
+    /**
+     * @return the event as JSON. Secrets are never written.
+     */
+    pub fn to_json(&self) -> String {
+        let mut object = self.details.clone();
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0);
+        object.insert(String::from("event"), Json::String(String::from(self.kind)));
+        object.insert(String::from("time"), Json::U64(time));
+        let fields = [("client", &self.client),
+                      ("author", &self.author),
+                      ("datatype", &self.datatype)];
+        for &(key, value) in &fields {
+            if let Some(ref value) = *value {
+                object.insert(String::from(key), Json::String(value.clone()));
+            }
+        }
+        Json::Object(object).to_string()
+    }
+}
+
+/**
+ * @return the data as JSON, without its secret
+ */
+pub fn redact(data: &RoriData) -> Json {
+    let mut object = Object::new();
+    object.insert(String::from("author"), Json::String(data.author.clone()));
+    object.insert(String::from("content"), Json::String(data.content.clone()));
+    object.insert(String::from("client"), Json::String(data.client.clone()));
+    object.insert(String::from("datatype"), Json::String(data.datatype.clone()));
+    let secret = if data.secret.len() == 0 { "" } else { "[redacted]" };
+    object.insert(String::from("secret"), Json::String(String::from(secret)));
+    Json::Object(object)
+}
+
+/**
+ * Which events a subscriber wants. None matches everything.
+ */
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct EventFilter {
+    pub author: Option<String>,
+    pub client: Option<String>,
+    pub datatype: Option<String>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &Event) -> bool {
+        let accept = |wanted: &Option<String>, value: &Option<String>| match (wanted, value) {
+            (&Some(ref wanted), &Some(ref value)) => wanted.to_lowercase() == value.to_lowercase(),
+            (&Some(_), &None) => false,
+            (&None, _) => true,
+        };
+        accept(&self.author, &event.author) && accept(&self.client, &event.client) &&
+        accept(&self.datatype, &event.datatype)
+    }
+}
+
+struct Subscriber {
+    id: u64,
+    filter: EventFilter,
+    sender: SyncSender<String>,
+    /// events dropped since the queue of the subscriber is full
+    dropped: usize,
+}
+
+/**
+ * Send events to subscribers (the GET /events streams)
+ */
+pub struct EventBus {
+    subscribers: Mutex<Vec<Subscriber>>,
+    next_id: AtomicUsize,
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus {
+            subscribers: Mutex::new(Vec::new()),
+            next_id: AtomicUsize::new(0),
+        }
+    }
+
+    /**
+     * @param filter: the events to receive
+     * @return the id of the subscriber and the events as JSON, or None if there are too many
+     * subscribers
+     */
+    pub fn subscribe(&self, filter: EventFilter) -> Option<(u64, Receiver<String>)> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if subscribers.len() >= MAX_SUBSCRIBERS {
+            return None;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) as u64;
+        let (sender, receiver) = sync_channel(SUBSCRIBER_QUEUE_SIZE);
+        subscribers.push(Subscriber {
+            id: id,
+            filter: filter,
+            sender: sender,
+            dropped: 0,
+        });
+        Some((id, receiver))
+    }
+
+    pub fn unsubscribe(&self, id: u64) {
+        self.subscribers.lock().unwrap().retain(|subscriber| subscriber.id != id);
+    }
+
+    /**
+     * Send an event to the subscribers which want it
+     * @param event: build the event, only called if someone listens
+     */
+    pub fn publish<F: FnOnce() -> Event>(&self, event: F) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if subscribers.len() == 0 {
+            return;
+        }
+        let event = event();
+        let mut json = None;
+        let mut gone = Vec::new();
+        for subscriber in subscribers.iter_mut() {
+            if !subscriber.filter.matches(&event) {
+                continue;
+            }
+            let json = json.get_or_insert_with(|| event.to_json()).clone();
+            match subscriber.sender.try_send(json) {
+                Ok(()) => {
+                    if subscriber.dropped > 0 {
+                        info!(target:"events", "Subscriber {} is back, {} events were dropped",
+                              subscriber.id, subscriber.dropped);
+                        subscriber.dropped = 0;
+                    }
+                }
+                Err(TrySendError::Full(_)) => {
+                    METRICS.event_dropped();
+                    if subscriber.dropped == 0 {
+                        warn!(target:"events", "Subscriber {} is too slow, dropping its events",
+                              subscriber.id);
+                    }
+                    subscriber.dropped += 1;
+                }
+                Err(TrySendError::Disconnected(_)) => gone.push(subscriber.id),
+            }
+        }
+        // Forget subscribers which are gone
+        subscribers.retain(|subscriber| !gone.contains(&subscriber.id));
+    }
+}
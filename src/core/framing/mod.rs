@@ -0,0 +1,196 @@
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::str::from_utf8;
+
+/**
+ * Version of the framing protocol spoken by this server
+ */
+pub const PROTOCOL_VERSION: u32 = 1;
+/**
+ * Default size limit of a frame body (1 MiB)
+ */
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+const MAGIC: &'static [u8] = b"RORI/";
+const MAX_HEADER_SIZE: usize = 128;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Frame {
+    pub version: u32,
+    pub id: Option<String>,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum FrameError {
+    Io(io::Error),
+    InvalidHeader(String),
+    UnsupportedVersion(u32),
+    TooLarge(usize),
+    InvalidUtf8,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrameError::Io(ref e) => write!(f, "io error: {}", e),
+            FrameError::InvalidHeader(ref h) => write!(f, "invalid frame header: {}", h),
+            FrameError::UnsupportedVersion(v) => write!(f, "unsupported protocol version: {}", v),
+            FrameError::TooLarge(size) => write!(f, "frame too large: {} bytes", size),
+            FrameError::InvalidUtf8 => write!(f, "frame body is not valid UTF-8"),
+        }
+    }
+}
+
+impl From<io::Error> for FrameError {
+    fn from(e: io::Error) -> FrameError {
+        FrameError::Io(e)
+    }
+}
+
+/**
+ * Read RoriData frames from a stream.
+ * A frame is a header line followed by a JSON body:
+ *     RORI/<version> <length>[ <id>]\n<body of length bytes>
+ * Several frames can be sent on the same connection. For compatibility, if the stream doesn't
+ * start with a header, the whole stream (until EOF) is read as one legacy message.
+ */
+pub struct FrameReader<S: Read> {
+    inner: BufReader<S>,
+    max_frame_size: usize,
+    finished: bool,
+}
+
+impl<S: Read> FrameReader<S> {
+    /**
+     * @param stream: the stream to read
+     * @param max_frame_size: the maximum size of a body
+     * @return a FrameReader
+     */
+    pub fn new(stream: S, max_frame_size: usize) -> FrameReader<S> {
+        FrameReader {
+            inner: BufReader::new(stream),
+            max_frame_size: max_frame_size,
+            finished: false,
+        }
+    }
+
+    /**
+     * @return the underlying stream (to write on it)
+     */
+    pub fn get_mut(&mut self) -> &mut S {
+        self.inner.get_mut()
+    }
+
+    /**
+     * Read the next frame
+     * @return None when the peer closed the connection
+     */
+    pub fn read_frame(&mut self) -> Result<Option<Frame>, FrameError> {
+        if self.finished {
+            return Ok(None);
+        }
+        let is_framed = {
+            let buffer = self.inner.fill_buf()?;
+            if buffer.len() == 0 {
+                self.finished = true;
+                return Ok(None);
+            }
+            let len = if buffer.len() < MAGIC.len() { buffer.len() } else { MAGIC.len() };
+            buffer[..len] == MAGIC[..len]
+        };
+        if is_framed {
+            self.read_framed()
+        } else {
+            self.read_legacy()
+        }
+    }
+
+    fn read_framed(&mut self) -> Result<Option<Frame>, FrameError> {
+        let mut header = Vec::new();
+        (&mut self.inner).take(MAX_HEADER_SIZE as u64).read_until(b'\n', &mut header)?;
+        if header.last() != Some(&b'\n') {
+            self.finished = true;
+            return Err(FrameError::InvalidHeader(String::from_utf8_lossy(&header).into_owned()));
+        }
+        let header = from_utf8(&header).map_err(|_| FrameError::InvalidUtf8)?.trim().to_string();
+        let (version, length, id) = FrameReader::<S>::parse_header(&header)?;
+        if version != PROTOCOL_VERSION {
+            self.finished = true;
+            return Err(FrameError::UnsupportedVersion(version));
+        }
+        if length > self.max_frame_size {
+            self.finished = true;
+            return Err(FrameError::TooLarge(length));
+        }
+        let mut body = vec![0u8; length];
+        self.inner.read_exact(&mut body)?;
+        let body = String::from_utf8(body).map_err(|_| FrameError::InvalidUtf8)?;
+        Ok(Some(Frame {
+            version: version,
+            id: id,
+            body: body,
+        }))
+    }
+
+    /**
+     * Old clients send one message and close the connection. The message can be padded with NUL
+     * bytes.
+     */
+    fn read_legacy(&mut self) -> Result<Option<Frame>, FrameError> {
+        self.finished = true;
+        let mut body = Vec::new();
+        let limit = self.max_frame_size as u64 + 1;
+        (&mut self.inner).take(limit).read_to_end(&mut body)?;
+        if body.len() > self.max_frame_size {
+            return Err(FrameError::TooLarge(body.len()));
+        }
+        if let Some(end) = body.iter().position(|b| *b == 0) {
+            body.truncate(end);
+        }
+        let body = String::from_utf8(body).map_err(|_| FrameError::InvalidUtf8)?;
+        Ok(Some(Frame {
+            version: 0,
+            id: None,
+            body: body,
+        }))
+    }
+
+    /**
+     * Parse "RORI/<version> <length>[ <id>]"
+     */
+    fn parse_header(header: &str) -> Result<(u32, usize, Option<String>), FrameError> {
+        let invalid = || FrameError::InvalidHeader(String::from(header));
+        let mut parts = header.split_whitespace();
+        let version = parts.next()
+            .and_then(|p| p.get(MAGIC.len()..))
+            .and_then(|v| v.parse::<u32>().ok())
+            .ok_or_else(&invalid)?;
+        let length = parts.next()
+            .and_then(|l| l.parse::<usize>().ok())
+            .ok_or_else(&invalid)?;
+        let id = parts.next().map(String::from);
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok((version, length, id))
+    }
+}
+
+/**
+ * Write a frame on a stream
+ * @param stream: where to write
+ * @param id: optional id of the frame
+ * @param body: the JSON to send
+ */
+pub fn write_frame<W: Write>(stream: &mut W, id: Option<&str>, body: &str) -> io::Result<()> {
+    let header = match id {
+        Some(id) => format!("RORI/{} {} {}\n", PROTOCOL_VERSION, body.len(), id),
+        None => format!("RORI/{} {}\n", PROTOCOL_VERSION, body.len()),
+    };
+    stream.write_all(header.as_bytes())?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/**
+ * Values of a metric, by labels
+ */
+type Series = BTreeMap<Vec<(&'static str, String)>, f64>;
+
+/**
+ * Counters of what the server does, exported in the Prometheus text format
+ */
+pub struct Metrics {
+    messages: Mutex<Series>,
+    auth_failures: Mutex<Series>,
+    module_executions: Mutex<Series>,
+    module_seconds: Mutex<Series>,
+    deliveries: Mutex<Series>,
+    delivery_failures: Mutex<Series>,
+    events_dropped: Mutex<Series>,
+}
+
+/**
+ * Values read when the metrics are rendered
+ */
+pub struct Gauges {
+    pub registered_endpoints: usize,
+    pub queue_depth: usize,
+    pub pending_deliveries: usize,
+    pub dead_letters: usize,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            messages: Mutex::new(BTreeMap::new()),
+            auth_failures: Mutex::new(BTreeMap::new()),
+            module_executions: Mutex::new(BTreeMap::new()),
+            module_seconds: Mutex::new(BTreeMap::new()),
+            deliveries: Mutex::new(BTreeMap::new()),
+            delivery_failures: Mutex::new(BTreeMap::new()),
+            events_dropped: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /**
+     * An authorized client sent a message
+     */
+    pub fn message_received(&self, client: &str, datatype: &str) {
+        add(&self.messages,
+            vec![("client", String::from(client)), ("datatype", String::from(datatype))],
+            1.);
+    }
+
+    /**
+     * A message was refused
+     * @param reason: unauthorized or forbidden
+     */
+    pub fn auth_failed(&self, reason: &str) {
+        add(&self.auth_failures, vec![("reason", String::from(reason))], 1.);
+    }
+
+    /**
+     * A module was executed
+     * @param module: the name of the module
+     * @param duration: how long the module ran
+     */
+    pub fn module_executed(&self, module: &str, duration: Duration) {
+        let labels = vec![("module", String::from(module))];
+        add(&self.module_executions, labels.clone(), 1.);
+        let seconds = duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9;
+        add(&self.module_seconds, labels, seconds);
+    }
+
+    /**
+     * Data was sent to an endpoint
+     * @param transport: how the data was sent (tls or websocket)
+     * @param success: false if the endpoint couldn't get the data
+     */
+    pub fn delivered(&self, transport: &str, success: bool) {
+        let series = if success { &self.deliveries } else { &self.delivery_failures };
+        add(series, vec![("transport", String::from(transport))], 1.);
+    }
+
+    /**
+     * An event was not sent to a subscriber (event stream or webhooks) which is too slow
+     */
+    pub fn event_dropped(&self) {
+        add(&self.events_dropped, Vec::new(), 1.);
+    }
+
+    /**
+     * @return all metrics in the Prometheus text format
+     */
+    pub fn render(&self, gauges: &Gauges) -> String {
+        let mut output = String::new();
+        render(&mut output,
+               "rori_messages_received_total",
+               "counter",
+               "Messages received from authorized clients",
+               &self.messages.lock().unwrap());
+        render(&mut output,
+               "rori_auth_failures_total",
+               "counter",
+               "Messages refused because the client is unauthorized or forbidden",
+               &self.auth_failures.lock().unwrap());
+        render(&mut output,
+               "rori_module_executions_total",
+               "counter",
+               "Executions of modules",
+               &self.module_executions.lock().unwrap());
+        render(&mut output,
+               "rori_module_duration_seconds_total",
+               "counter",
+               "Time spent in modules",
+               &self.module_seconds.lock().unwrap());
+        render(&mut output,
+               "rori_endpoint_deliveries_total",
+               "counter",
+               "Data sent to endpoints",
+               &self.deliveries.lock().unwrap());
+        render(&mut output,
+               "rori_endpoint_delivery_failures_total",
+               "counter",
+               "Failed attempts to send data to endpoints, including retried ones",
+               &self.delivery_failures.lock().unwrap());
+        render(&mut output,
+               "rori_events_dropped_total",
+               "counter",
+               "Events not sent to event streams and webhooks which were too slow",
+               &self.events_dropped.lock().unwrap());
+        let gauge = |value: usize| {
+            let mut series = BTreeMap::new();
+            series.insert(Vec::new(), value as f64);
+            series
+        };
+        render(&mut output,
+               "rori_registered_endpoints",
+               "gauge",
+               "Registered endpoints",
+               &gauge(gauges.registered_endpoints));
+        render(&mut output,
+               "rori_worker_queue_depth",
+               "gauge",
+               "Messages waiting for a worker",
+               &gauge(gauges.queue_depth));
+        render(&mut output,
+               "rori_pending_deliveries",
+               "gauge",
+               "Data waiting to be sent to endpoints",
+               &gauge(gauges.pending_deliveries));
+        render(&mut output,
+               "rori_dead_letters",
+               "gauge",
+               "Data kept as dead letters after every attempt to send it failed",
+               &gauge(gauges.dead_letters));
+        output
+    }
+}
+
+fn add(series: &Mutex<Series>, labels: Vec<(&'static str, String)>, value: f64) {
+    *series.lock().unwrap().entry(labels).or_insert(0.) += value;
+}
+
+/**
+ * Escape a label value (backslashes, quotes and new lines)
+ */
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn render(output: &mut String, name: &str, kind: &str, help: &str, series: &Series) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, kind);
+    for (labels, value) in series {
+        let labels: Vec<String> = labels.iter()
+            .map(|&(key, ref value)| format!("{}=\"{}\"", key, escape(value)))
+            .collect();
+        if labels.len() == 0 {
+            let _ = writeln!(output, "{} {}", name, value);
+        } else {
+            let _ = writeln!(output, "{}{{{}}} {}", name, labels.join(","), value);
+        }
+    }
+}
@@ -1,236 +1,1457 @@
 
+pub mod api_auth;
+pub mod api_error;
+pub mod auth_manager;
+pub mod config;
+pub mod delivery;
 pub mod endpoint_manager;
+pub mod envelope;
+pub mod events;
+pub mod framing;
+pub mod metrics;
 pub mod module_manager;
+pub mod openapi;
+pub mod rate_limiter;
+pub mod reply;
+pub mod signals;
+pub mod webhooks;
+pub mod websocket;
 pub mod words_manager;
+pub mod worker_pool;
 
-use crypto::digest::Digest;
-use crypto::sha2::Sha256;
+use core::api_auth::{ApiScope, TokenManager};
+use core::api_error::{ApiError, json_response};
+use core::auth_manager::{AuthManager, AuthorizedUser, PeerIdentity};
+use core::config::{Config, Listener};
+use core::delivery::DeliveryQueue;
 use core::module_manager::ModuleManager;
-use core::endpoint_manager::EndpointManager;
+use core::rate_limiter::RateLimiter;
+use core::reply::{Reply, ReplyStatus};
+use core::endpoint_manager::{EndpointManager, Liveness, Pusher};
+use core::envelope::Envelope;
+use core::events::{Event, EventBus, EventFilter, redact};
+use core::framing::{PROTOCOL_VERSION, Frame, FrameError, FrameReader, write_frame};
+use core::metrics::{Gauges, Metrics};
+use core::webhooks::WebhookDispatcher;
+use core::websocket::{Message, WebSocket};
 use core::words_manager::WordsManager;
+use core::worker_pool::{PoolStats, WorkerPool};
 use iron::prelude::*;
 use iron::status;
-use openssl::ssl::{Ssl, SslContext, SslMethod, SslStream, SslVerifyMode, SslFiletype};
+use iron::typemap;
+use openssl::ssl::{Ssl, SslContext, SslMethod, SslVerifyMode, SslFiletype};
 use rori_utils::data::RoriData;
 use router::Router;
 use rustc_serialize::json::{self, decode};
-use std::fs::File;
+use std::fs;
+use std::io::{self, ErrorKind};
 use std::io::prelude::*;
-use std::net::{TcpListener, TcpStream};
-use std::path::Path;
-use std::str::from_utf8;
-use std::sync::Mutex;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use iron::{BeforeMiddleware, Handler, Listening};
+use iron::headers::{Authorization, Basic, Bearer, ContentType};
+use iron::modifiers::Header;
+use iron::response::WriteBody;
+use std::error::Error;
+use std::fmt;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/**
+ * How often data pushed to WebSocket clients is sent (in milliseconds)
+ */
+const PUSH_INTERVAL: u64 = 100;
+
+/**
+ * Event streams keep their API thread: at most 1/EVENT_STREAMS_SHARE of the threads can stream
+ * events, so other routes (like /healthz) are still answered
+ */
+const EVENT_STREAMS_SHARE: usize = 4;
+
+/**
+ * Time given to a client to finish the TLS handshake (in seconds)
+ */
+const HANDSHAKE_TIMEOUT: u64 = 10;
+
+/**
+ * A comment is sent on idle event streams after this delay (in seconds)
+ */
+const EVENTS_KEEP_ALIVE: u64 = 15;
 
 lazy_static! {
     pub static ref ENDPOINTMANAGER: Mutex<EndpointManager> = Mutex::new(EndpointManager::new());
+    pub static ref WORKERSTATS: Arc<PoolStats> = Arc::new(PoolStats::default());
+    pub static ref RATELIMITER: RateLimiter = RateLimiter::new();
+    pub static ref APITOKENS: TokenManager = TokenManager::new();
+    pub static ref METRICS: Metrics = Metrics::new();
+    pub static ref EVENTS: EventBus = EventBus::new();
+    pub static ref DELIVERIES: DeliveryQueue = DeliveryQueue::new();
+    static ref CONNECTIONS: Connections = Connections::new();
+}
+
+/**
+ * Set when RORI is stopping
+ */
+pub static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/**
+ * Set while the Server accepts connections
+ */
+pub static LISTENING: AtomicBool = AtomicBool::new(false);
+
+/**
+ * Number of API requests being processed
+ */
+static API_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+/**
+ * Number of GET /events streams, and how many the API threads allow
+ */
+static EVENT_STREAMS: AtomicUsize = AtomicUsize::new(0);
+static MAX_EVENT_STREAMS: AtomicUsize = AtomicUsize::new(1);
+
+/**
+ * Set when config_server.json must be reloaded
+ */
+pub static RELOAD: AtomicBool = AtomicBool::new(false);
+
+/**
+ * Ask the Server to reload config_server.json
+ */
+pub fn reload() {
+    info!(target:"Server", "Reloading configuration...");
+    RELOAD.store(true, Ordering::SeqCst);
+}
+
+/**
+ * Stop the Server and the API. Messages being processed are finished, but clients can't send new
+ * messages.
+ */
+pub fn shutdown() {
+    info!(target:"Server", "Shutting down...");
+    SHUTDOWN.store(true, Ordering::SeqCst);
+    CONNECTIONS.close_reads();
+}
+
+/**
+ * The socket under a client stream, to stop reading it on shutdown
+ */
+enum Socket {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Socket {
+    fn try_clone(&self) -> io::Result<Socket> {
+        match *self {
+            Socket::Tcp(ref socket) => socket.try_clone().map(Socket::Tcp),
+            Socket::Unix(ref socket) => socket.try_clone().map(Socket::Unix),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match *self {
+            Socket::Tcp(ref socket) => socket.set_read_timeout(timeout),
+            Socket::Unix(ref socket) => socket.set_read_timeout(timeout),
+        }
+    }
+
+    fn shutdown_read(&self) {
+        let _ = match *self {
+            Socket::Tcp(ref socket) => socket.shutdown(Shutdown::Read),
+            Socket::Unix(ref socket) => socket.shutdown(Shutdown::Read),
+        };
+    }
+}
+
+/**
+ * Sockets of connected clients, to stop reading them on shutdown
+ */
+struct Connections {
+    sockets: Mutex<(usize, HashMap<usize, Socket>)>,
+}
+
+impl Connections {
+    fn new() -> Connections {
+        Connections { sockets: Mutex::new((0, HashMap::new())) }
+    }
+
+    /**
+     * @return the id of the connection
+     */
+    fn add(&self, socket: &Socket) -> usize {
+        let mut sockets = self.sockets.lock().unwrap();
+        sockets.0 += 1;
+        let id = sockets.0;
+        if SHUTDOWN.load(Ordering::SeqCst) {
+            socket.shutdown_read();
+        } else if let Ok(socket) = socket.try_clone() {
+            sockets.1.insert(id, socket);
+        }
+        id
+    }
+
+    fn remove(&self, id: usize) {
+        self.sockets.lock().unwrap().1.remove(&id);
+    }
+
+    /**
+     * @return the number of connected clients
+     */
+    fn count(&self) -> usize {
+        self.sockets.lock().unwrap().1.len()
+    }
+
+    /**
+     * Make clients reading the end of their stream
+     */
+    fn close_reads(&self) {
+        let sockets = self.sockets.lock().unwrap();
+        for socket in sockets.1.values() {
+            socket.shutdown_read();
+        }
+    }
+}
+
+/**
+ * A connection with a client, whatever its transport
+ */
+trait ClientStream: Read + Write + Send {}
+
+impl<S: Read + Write + Send> ClientStream for S {}
+
+/**
+ * How messages are read from a client
+ */
+enum Transport {
+    /// RORI frames (see core::framing)
+    Framed(FrameReader<Box<dyn ClientStream>>),
+    /// One message per WebSocket message, and data pushed to endpoints registered by the client
+    WebSocket(WebSocket<Box<dyn ClientStream>>, Receiver<String>, Pusher),
+}
+
+/**
+ * A message read from a client, processed by a worker of the pool
+ */
+struct Task {
+    peer: Option<PeerIdentity>,
+    pusher: Option<Pusher>,
+    id: Option<String>,
+    body: String,
+    /// where the reply is sent back to the connection
+    reply: Sender<Reply>,
+}
+
+/**
+ * The worker pool of a running Server, shared with the API so data sent to /ingest is processed
+ * like data sent to the Server
+ */
+pub struct Workers {
+    pool: RwLock<Option<Arc<WorkerPool<Task>>>>,
+}
+
+impl Workers {
+    fn new(pool: Option<WorkerPool<Task>>) -> Workers {
+        Workers { pool: RwLock::new(pool.map(Arc::new)) }
+    }
+
+    /**
+     * Give a message to the pool and wait for its reply
+     * @param id: the id of the message
+     * @param body: the message
+     * @return the reply, busy if the queue is full or if the Server is not running
+     */
+    fn process(&self, id: Option<String>, body: String) -> Reply {
+        let pool = match *self.pool.read().unwrap() {
+            Some(ref pool) => pool.clone(),
+            None => return Reply::new(id, ReplyStatus::Busy),
+        };
+        let (sender, receiver) = channel();
+        let task = Task {
+            peer: None,
+            pusher: None,
+            id: id.clone(),
+            body: body,
+            reply: sender,
+        };
+        let submitted = pool.submit(task);
+        // The Server must be the only owner of the pool to stop it
+        drop(pool);
+        match submitted {
+            Ok(()) => receiver.recv().unwrap_or_else(|_| Reply::new(id, ReplyStatus::Busy)),
+            Err(_) => {
+                let stats = WORKERSTATS.snapshot();
+                warn!(target:"API", "Too many messages ({} waiting, {} rejected)",
+                      stats.queue_depth, stats.rejected);
+                Reply::new(id, ReplyStatus::Busy)
+            }
+        }
+    }
 }
 
 struct Client {
-    stream: SslStream<TcpStream>,
+    transport: Transport,
+    peer: Option<PeerIdentity>,
+    connection: usize,
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        CONNECTIONS.remove(self.connection);
+        if let Transport::WebSocket(..) = self.transport {
+            ENDPOINTMANAGER.lock().unwrap().remove_pushed_endpoints(self.connection);
+        }
+    }
 }
 
 impl Client {
-    fn new(stream: SslStream<TcpStream>) -> Client {
-        return Client { stream: stream };
+    /**
+     * @param stream: where messages are read and replies written
+     * @param socket: the socket under the stream
+     * @param connection: the id of the connection in CONNECTIONS
+     * @param peer: the identity of the client if it sent a certificate
+     * @param max_frame_size: the maximum size of a message
+     * @param websocket: if the client speaks WebSocket
+     */
+    fn new(stream: Box<dyn ClientStream>,
+           socket: &Socket,
+           connection: usize,
+           peer: Option<PeerIdentity>,
+           max_frame_size: usize,
+           websocket: bool)
+           -> io::Result<Client> {
+        let transport = if websocket {
+            // Reads time out to send pushed data
+            socket.set_read_timeout(Some(Duration::from_millis(PUSH_INTERVAL)))?;
+            let (sender, receiver) = channel();
+            let pusher = Pusher {
+                connection: connection,
+                sender: sender,
+            };
+            Transport::WebSocket(WebSocket::new(stream, max_frame_size), receiver, pusher)
+        } else {
+            Transport::Framed(FrameReader::new(stream, max_frame_size))
+        };
+        Ok(Client {
+            transport: transport,
+            peer: peer,
+            connection: connection,
+        })
     }
 
-    fn read(&mut self) -> String {
-        let mut result = String::from("");
-        let mut buffer = [0u8; 512];
+    /**
+     * @return where to push data for endpoints registered by this client, if supported
+     */
+    fn pusher(&self) -> Option<Pusher> {
+        match self.transport {
+            Transport::WebSocket(_, _, ref pusher) => Some(pusher.clone()),
+            Transport::Framed(_) => None,
+        }
+    }
+
+    /**
+     * Read the next message sent by the client
+     * @return None if the client closed the connection
+     */
+    fn read(&mut self) -> Result<Option<Frame>, FrameError> {
+        let (socket, pushed) = match self.transport {
+            Transport::Framed(ref mut reader) => return reader.read_frame(),
+            Transport::WebSocket(ref mut socket, ref pushed, _) => (socket, pushed),
+        };
         loop {
-            let usize = self.stream.read(&mut buffer).unwrap();
-            if usize == 0 {
-                break;
+            while let Ok(data) = pushed.try_recv() {
+                socket.send_text(&*data)?;
             }
-            let msg = from_utf8(&buffer).unwrap();
-            result.push_str(msg);
+            let body = match socket.poll()? {
+                Some(Message::Text(body)) => body,
+                Some(Message::Binary(body)) => {
+                    String::from_utf8(body).map_err(|_| FrameError::InvalidUtf8)?
+                }
+                Some(Message::Close) => return Ok(None),
+                None => continue,
+            };
+            return Ok(Some(Frame {
+                version: PROTOCOL_VERSION,
+                id: None,
+                body: body,
+            }));
+        }
+    }
+
+    /**
+     * Send a reply to the client
+     */
+    fn reply(&mut self, reply: &Reply) {
+        let result = match self.transport {
+            Transport::Framed(ref mut reader) => {
+                write_frame(reader.get_mut(), Some(&*reply.id), &*reply.to_json())
+            }
+            Transport::WebSocket(ref mut socket, _, _) => socket.send_text(&*reply.to_json()),
+        };
+        if let Err(e) = result {
+            error!(target:"Server", "Can't send reply to client: {}", e);
         }
+    }
+}
+
+/**
+ * A bound listener of the Server
+ */
+enum Acceptor {
+    Tcp {
+        listener: TcpListener,
+        tls: bool,
+        websocket: bool,
+    },
+    Unix(UnixListener, PathBuf),
+}
+
+impl Acceptor {
+    fn bind(listener: &Listener) -> io::Result<Acceptor> {
+        let (address, tls, websocket) = match *listener {
+            Listener::Tls(address) => (address, true, false),
+            Listener::Plain(address) => (address, false, false),
+            Listener::Wss(address) => (address, true, true),
+            Listener::Ws(address) => (address, false, true),
+            Listener::Unix(ref path, mode) => {
+                match fs::symlink_metadata(path) {
+                    Ok(ref metadata) if metadata.file_type().is_socket() => {
+                        // Remove the socket of a previous run, unless a server still listens
+                        if UnixStream::connect(path).is_ok() {
+                            return Err(io::Error::new(ErrorKind::AddrInUse,
+                                                      format!("{} is used by another server",
+                                                              path.display())));
+                        }
+                        fs::remove_file(path)?;
+                    }
+                    Ok(_) => {
+                        return Err(io::Error::new(ErrorKind::AlreadyExists,
+                                                  format!("{} exists and is not a socket",
+                                                          path.display())))
+                    }
+                    Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+                let unix_listener = Acceptor::bind_unix(path, mode)?;
+                unix_listener.set_nonblocking(true)?;
+                return Ok(Acceptor::Unix(unix_listener, path.clone()));
+            }
+        };
+        let tcp_listener = TcpListener::bind(address)?;
+        tcp_listener.set_nonblocking(true)?;
+        Ok(Acceptor::Tcp {
+            listener: tcp_listener,
+            tls: tls,
+            websocket: websocket,
+        })
+    }
+
+    /**
+     * Bind a Unix socket in a private directory, and link it to path once it has its mode, so
+     * nobody can connect before. Fails if path already exists.
+     * @param path: where the socket is created
+     * @param mode: the permissions of the socket
+     */
+    fn bind_unix(path: &Path, mode: Option<u32>) -> io::Result<UnixListener> {
+        let mut private_dir = path.as_os_str().to_os_string();
+        private_dir.push(format!(".{}.tmp", process::id()));
+        let private_dir = PathBuf::from(private_dir);
+        fs::DirBuilder::new().mode(0o700).create(&private_dir)?;
+        let private_path = private_dir.join("socket");
+        let result = UnixListener::bind(&private_path).and_then(|listener| {
+            if let Some(mode) = mode {
+                fs::set_permissions(&private_path, fs::Permissions::from_mode(mode))?;
+            }
+            // Unlike a rename, a link never replaces an existing file
+            fs::hard_link(&private_path, path)?;
+            Ok(listener)
+        });
+        let _ = fs::remove_file(&private_path);
+        let _ = fs::remove_dir(&private_dir);
         result
     }
+
+    /**
+     * Accept a pending client. The TLS handshake is done later by Connection::open, so a slow
+     * client doesn't block other listeners.
+     * @return the connection, None if nobody is waiting
+     */
+    fn accept(&self) -> io::Result<Option<Connection>> {
+        let (socket, tls, websocket) = match *self {
+            Acceptor::Tcp { ref listener, tls, websocket } => {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+                    Err(e) => return Err(e),
+                };
+                stream.set_nonblocking(false)?;
+                (Socket::Tcp(stream), tls, websocket)
+            }
+            Acceptor::Unix(ref listener, _) => {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+                    Err(e) => return Err(e),
+                };
+                stream.set_nonblocking(false)?;
+                (Socket::Unix(stream), false, false)
+            }
+        };
+        Ok(Some(Connection {
+            socket: socket,
+            tls: tls,
+            websocket: websocket,
+        }))
+    }
 }
 
-#[derive(Clone, RustcDecodable, RustcEncodable, Default, PartialEq, Debug)]
-struct AuthorizedUser {
-    pub name: Option<String>,
-    pub secret: Option<String>,
+/**
+ * A connection accepted by an Acceptor, before the TLS handshake
+ */
+struct Connection {
+    socket: Socket,
+    tls: bool,
+    websocket: bool,
+}
+
+impl Connection {
+    /**
+     * Tell the client that RORI is busy and close the connection, without the TLS handshake
+     */
+    fn refuse(self) {
+        let busy = Reply::new(None, ReplyStatus::Busy);
+        let result = match self.socket {
+            // Nothing can be sent before the TLS handshake
+            Socket::Tcp(_) if self.tls => Ok(()),
+            Socket::Tcp(stream) if self.websocket => WebSocket::new(stream, 0).refuse(),
+            Socket::Tcp(mut stream) => write_frame(&mut stream, Some(&*busy.id), &*busy.to_json()),
+            Socket::Unix(mut stream) => write_frame(&mut stream, Some(&*busy.id), &*busy.to_json()),
+        };
+        if let Err(e) = result {
+            error!(target:"Server", "Can't refuse client: {}", e);
+        }
+    }
+
+    /**
+     * Do the TLS handshake if needed
+     * @param connection: the id of the connection in CONNECTIONS
+     * @param ssl: the context for TLS listeners
+     * @param max_frame_size: the maximum size of a message
+     * @return the client, None if the handshake failed
+     */
+    fn open(self,
+            connection: usize,
+            ssl: Option<SslContext>,
+            max_frame_size: usize)
+            -> io::Result<Option<Client>> {
+        let socket = self.socket.try_clone()?;
+        let stream = match self.socket {
+            Socket::Tcp(stream) => stream,
+            Socket::Unix(stream) => {
+                let stream = Box::new(stream);
+                return Client::new(stream, &socket, connection, None, max_frame_size, false)
+                    .map(Some);
+            }
+        };
+        if !self.tls {
+            return Client::new(Box::new(stream),
+                               &socket,
+                               connection,
+                               None,
+                               max_frame_size,
+                               self.websocket)
+                .map(Some);
+        }
+        // A client which never finishes the handshake must not keep its thread
+        let timeout = Some(Duration::from_secs(HANDSHAKE_TIMEOUT));
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        let ssl_stream = match ssl.map(|ssl| Ssl::new(&ssl)) {
+            Some(Ok(ssl)) => ssl.accept(stream).ok(),
+            _ => None,
+        };
+        let ssl_stream = match ssl_stream {
+            Some(ssl_stream) => ssl_stream,
+            None => {
+                error!(target:"Server", "Can't create SslStream");
+                return Ok(None);
+            }
+        };
+        ssl_stream.get_ref().set_read_timeout(None)?;
+        ssl_stream.get_ref().set_write_timeout(None)?;
+        let peer = ssl_stream.ssl()
+            .peer_certificate()
+            .map(|cert| PeerIdentity::from_certificate(&cert));
+        Client::new(Box::new(ssl_stream),
+                    &socket,
+                    connection,
+                    peer,
+                    max_frame_size,
+                    self.websocket)
+            .map(Some)
+    }
 }
 
-#[derive(Clone, RustcDecodable, RustcEncodable, Default, PartialEq, Debug)]
-struct ConfigServer {
-    pub ip: Option<String>,
-    pub port: Option<String>,
-    pub cert: Option<String>,
-    pub key: Option<String>,
-    pub authorize: Vec<AuthorizedUser>,
+impl Drop for Acceptor {
+    fn drop(&mut self) {
+        if let Acceptor::Unix(_, ref path) = *self {
+            if fs::symlink_metadata(path).map(|metadata| metadata.file_type().is_socket())
+                .unwrap_or(false) {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
 }
 
 pub struct Server {
-    address: String,
-    cert: String,
-    key: String,
-    authorize: Vec<AuthorizedUser>,
+    path: PathBuf,
+    config_modified: Option<SystemTime>,
+    config: Config,
+    auth_manager: Arc<RwLock<Arc<AuthManager>>>,
+    workers: Arc<Workers>,
 }
 
 impl Server {
-    pub fn new<P: AsRef<Path>>(config: P) -> Server {
-        // Configure from file
-        let mut file = File::open(config)
-            .ok()
-            .expect("Config file not found");
-        let mut data = String::new();
-        file.read_to_string(&mut data)
-            .ok()
-            .expect("failed to read!");
-        let params: ConfigServer = decode(&data[..]).unwrap();
-        let address = format!("{}:{}",
-                              &params.ip.unwrap_or(String::from("")),
-                              &params.port.unwrap_or(String::from("")));
+    /**
+     * @param path: path of config_server.json. Panics if the configuration is invalid.
+     */
+    pub fn new<P: AsRef<Path>>(path: P) -> Server {
+        let config = Config::load(path.as_ref())
+            .unwrap_or_else(|e| panic!("Invalid configuration:\n{}", e));
+        Server::from_config(path, config)
+    }
 
+    /**
+     * @param path: path of config_server.json, to reload it
+     * @param config: the configuration read from path
+     */
+    pub fn from_config<P: AsRef<Path>>(path: P, config: Config) -> Server {
+        let auth_manager = Server::build_auth_manager(&config);
         Server {
-            address: address,
-            cert: params.cert.unwrap_or(String::from("")),
-            key: params.key.unwrap_or(String::from("")),
-            authorize: params.authorize,
+            path: path.as_ref().to_path_buf(),
+            config_modified: Server::config_modified(path.as_ref()),
+            config: config,
+            auth_manager: Arc::new(RwLock::new(Arc::new(auth_manager))),
+            workers: Arc::new(Workers::new(None)),
+        }
+    }
+
+    fn config_modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    /**
+     * @return the AuthManager of the Server, replaced when the configuration is reloaded
+     */
+    pub fn auth_manager(&self) -> Arc<RwLock<Arc<AuthManager>>> {
+        self.auth_manager.clone()
+    }
+
+    /**
+     * @return the worker pool of the Server, set while the Server is running
+     */
+    pub fn workers(&self) -> Arc<Workers> {
+        self.workers.clone()
+    }
+
+    /**
+     * @param config: the size of the pool
+     * @param auth_manager: used to authorize messages
+     * @return a pool processing messages
+     */
+    fn build_pool(config: &Config, auth_manager: Arc<RwLock<Arc<AuthManager>>>) -> WorkerPool<Task> {
+        WorkerPool::new(config.workers,
+                        config.queue_size,
+                        config.overflow,
+                        WORKERSTATS.clone(),
+                        move |task| Server::process_task(&auth_manager, task))
+    }
+
+    fn build_auth_manager(config: &Config) -> AuthManager {
+        let mut auth_manager = AuthManager::new(config.authorize.clone());
+        auth_manager.set_replay_protection(config.max_clock_skew.as_secs(), config.nonce_cache_size);
+        auth_manager
+    }
+
+    /**
+     * Load certificates and key used for new connections
+     * @return the SSL context (None without certificate) or why it can't be built
+     */
+    fn build_ssl_context(config: &Config) -> Result<Option<SslContext>, String> {
+        let (cert, key) = match (&config.cert, &config.key) {
+            (&Some(ref cert), &Some(ref key)) => (cert, key),
+            (&None, &None) => return Ok(None),
+            _ => return Err(String::from("cert and key must be set together")),
+        };
+        let mut ssl_context = SslContext::builder(SslMethod::tls()).map_err(|e| e.to_string())?;
+        ssl_context.set_certificate_file(cert, SslFiletype::PEM)
+            .map_err(|e| format!("Can't set certificate file {}: {}", cert.display(), e))?;
+        match config.client_ca {
+            Some(ref client_ca) => {
+                // Mutual TLS: clients can authenticate with a certificate signed by this CA
+                ssl_context.set_ca_file(client_ca)
+                    .map_err(|e| format!("Can't set client CA file {}: {}", client_ca.display(), e))?;
+                let mut mode = SslVerifyMode::PEER;
+                if config.require_client_cert {
+                    mode |= SslVerifyMode::FAIL_IF_NO_PEER_CERT;
+                }
+                ssl_context.set_verify(mode);
+            }
+            None => ssl_context.set_verify(SslVerifyMode::NONE),
+        }
+        ssl_context.set_private_key_file(key, SslFiletype::PEM)
+            .map_err(|e| format!("Can't set private key {}: {}", key.display(), e))?;
+        ssl_context.check_private_key()
+            .map_err(|e| format!("{} doesn't match {}: {}", key.display(), cert.display(), e))?;
+        Ok(Some(ssl_context.build()))
+    }
+
+    /**
+     * Read config_server.json again. The authorize list (with rate limits), the replay protection
+     * and TLS files are replaced for new messages. If the new configuration is invalid, the
+     * current one is kept.
+     * @return the SSL context to use for new connections or why the configuration is rejected
+     */
+    pub fn reload(&mut self) -> Result<Option<SslContext>, String> {
+        self.config_modified = Server::config_modified(&self.path);
+        let config = Config::load(&self.path).map_err(|e| e.to_string())?;
+        let ssl = Server::build_ssl_context(&config)?;
+        if config.listeners != self.config.listeners || config.workers != self.config.workers ||
+           config.queue_size != self.config.queue_size {
+            warn!(target:"Server", "RORI must be restarted to change its listeners or its workers");
+        }
+
+        let mut auth_manager = Server::build_auth_manager(&config);
+        {
+            let mut current = self.auth_manager.write().unwrap();
+            if config.nonce_cache_size == self.config.nonce_cache_size {
+                auth_manager.keep_nonces(&current);
+            }
+            *current = Arc::new(auth_manager);
+        }
+        // Keep settings used by running threads
+        self.config.cert = config.cert;
+        self.config.key = config.key;
+        self.config.client_ca = config.client_ca;
+        self.config.require_client_cert = config.require_client_cert;
+        self.config.max_clock_skew = config.max_clock_skew;
+        self.config.nonce_cache_size = config.nonce_cache_size;
+        self.config.authorize = config.authorize;
+        self.config.max_connections = config.max_connections;
+        info!(target:"Server", "{} reloaded", self.path.display());
+        Ok(ssl)
+    }
+
+    /**
+     * @return if config_server.json should be reloaded (SIGHUP received or file modified)
+     */
+    fn should_reload(&self) -> bool {
+        if RELOAD.swap(false, Ordering::SeqCst) {
+            return true;
+        }
+        let modified = Server::config_modified(&self.path);
+        modified.is_some() && modified != self.config_modified
+    }
+
+    /**
+     * Restore dead letters and endpoints saved by the previous run. Must be done before the API
+     * listens, so ids of restored endpoints are not given to new endpoints.
+     * @return why the saved files can't be restored
+     */
+    pub fn restore(&self) -> Result<(), String> {
+        ENDPOINTMANAGER.lock().unwrap().set_lease(self.config.endpoint_lease);
+        DELIVERIES.set_retries(self.config.delivery_retries, Duration::from_secs(1));
+        if let Some(ref dead_letters_file) = self.config.dead_letters_file {
+            DELIVERIES.open(dead_letters_file)
+                .map_err(|e| {
+                    format!("Can't restore dead letters from {}: {}", dead_letters_file.display(), e)
+                })?;
+        }
+        if let Some(ref endpoints_file) = self.config.endpoints_file {
+            let count = ENDPOINTMANAGER.lock()
+                .unwrap()
+                .open(endpoints_file)
+                .map_err(|e| {
+                    format!("Can't restore endpoints from {}: {}", endpoints_file.display(), e)
+                })?;
+            info!(target:"Server", "{} endpoints restored from {}", count, endpoints_file.display());
         }
+        Ok(())
     }
 
     pub fn start(&mut self) {
-        let listener = TcpListener::bind(&*self.address).unwrap();
-        let mut ssl_context = SslContext::builder(SslMethod::tls()).unwrap();
-        match ssl_context.set_certificate_file(&*self.cert.clone(), SslFiletype::PEM) {
-            Ok(_) => info!(target:"Server", "Certificate set"),
-            Err(_) => error!(target:"Server", "Can't set certificate file"),
-        };
-        ssl_context.set_verify(SslVerifyMode::NONE);
-        match ssl_context.set_private_key_file(&*self.key.clone(), SslFiletype::PEM) {
-            Ok(_) => info!(target:"Server", "Private key set"),
-            Err(_) => error!(target:"Server", "Can't set private key"),
-        };
-        let ssl = ssl_context.build();
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    let ssl_stream = Ssl::new(&ssl).unwrap().accept(stream);
-                    let ssl_ok = match ssl_stream {
-                        Ok(_) => true,
-                        Err(_) => false,
-                    };
-                    if ssl_ok {
-                        let ssl_stream = ssl_stream.unwrap();
-                        let client = Client::new(ssl_stream);
-                        self.handle_client(client);
-                    } else {
-                        error!(target:"Server", "Can't create SslStream");
+        let mut acceptors = Vec::new();
+        for listener in &self.config.listeners {
+            match Acceptor::bind(listener) {
+                Ok(acceptor) => acceptors.push(acceptor),
+                Err(e) => {
+                    error!(target:"Server", "Can't listen on {:?}: {}", listener, e);
+                    return;
+                }
+            }
+        }
+        let mut ssl = match Server::build_ssl_context(&self.config) {
+            Ok(ssl) => ssl,
+            Err(e) => {
+                error!(target:"Server", "{}", e);
+                return;
+            }
+        };
+        // Workers process messages, connections are read by their own thread
+        let pool = Arc::new(Server::build_pool(&self.config, self.auth_manager.clone()));
+        *self.workers.pool.write().unwrap() = Some(pool.clone());
+        LISTENING.store(true, Ordering::SeqCst);
+        let webhooks = if self.config.webhooks.len() > 0 {
+            EVENTS.subscribe(EventFilter::default()).map(|(id, events)| {
+                WebhookDispatcher::new(self.config.webhooks.clone(), self.config.webhook_retries)
+                    .spawn(events);
+                id
+            })
+        } else {
+            None
+        };
+        let mut last_expiry = Instant::now();
+        // Poll listeners to see when we need to stop
+        while !SHUTDOWN.load(Ordering::SeqCst) {
+            if last_expiry.elapsed() >= Duration::from_secs(1) {
+                ENDPOINTMANAGER.lock().unwrap().expire();
+                last_expiry = Instant::now();
+            }
+            if self.should_reload() {
+                match self.reload() {
+                    Ok(new_ssl) => ssl = new_ssl,
+                    Err(e) => error!(target:"Server", "Configuration rejected: {}", e),
+                }
+            }
+            let mut accepted = false;
+            for acceptor in &acceptors {
+                match acceptor.accept() {
+                    Ok(Some(connection)) => {
+                        accepted = true;
+                        // Refused before having a thread, so idle clients can't exhaust threads
+                        if CONNECTIONS.count() >= self.config.max_connections {
+                            WORKERSTATS.rejected.fetch_add(1, Ordering::SeqCst);
+                            warn!(target:"Server", "Too many clients ({} connected)",
+                                  self.config.max_connections);
+                            connection.refuse();
+                            continue;
+                        }
+                        let id = CONNECTIONS.add(&connection.socket);
+                        let pool = pool.clone();
+                        let ssl = ssl.clone();
+                        let max_frame_size = self.config.max_frame_size;
+                        thread::spawn(move || {
+                            Server::serve(&pool, connection, id, ssl, max_frame_size)
+                        });
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        error!(target:"server", "Connection failed because {}", e);
                     }
                 }
+            }
+            if !accepted {
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+
+        drop(acceptors);
+        LISTENING.store(false, Ordering::SeqCst);
+        if let Some(webhooks) = webhooks {
+            EVENTS.unsubscribe(webhooks);
+        }
+        // Let clients get the reply of their last message, then let workers finish
+        let deadline = Instant::now().checked_add(self.config.shutdown_timeout);
+        let waiting = || deadline.map_or(true, |deadline| Instant::now() < deadline);
+        while CONNECTIONS.count() > 0 && waiting() {
+            thread::sleep(Duration::from_millis(50));
+        }
+        // The API may still be giving a message to the pool
+        self.workers.pool.write().unwrap().take();
+        let mut pool = pool;
+        let drained = loop {
+            match Arc::try_unwrap(pool) {
+                Ok(pool) => {
+                    let remaining = deadline.map_or(self.config.shutdown_timeout, |deadline| {
+                        deadline.saturating_duration_since(Instant::now())
+                    });
+                    break pool.shutdown(remaining);
+                }
+                Err(shared) if waiting() => pool = shared,
+                Err(_) => break false,
+            }
+            thread::sleep(Duration::from_millis(50));
+        };
+        if !drained {
+            warn!(target:"Server", "Some messages are still processed, stop anyway");
+        }
+    }
+
+    /**
+     * Take a token from the buckets of the client and of the author
+     * @param user: the client which sent the data
+     * @param data: the data received
+     * @return if the data can be processed
+     */
+    fn within_rate_limits(user: &AuthorizedUser, data: &RoriData) -> bool {
+        let client = user.name.clone().unwrap_or(String::from("")).to_lowercase();
+        if let Some(ref limit) = user.rate_limit {
+            if !RATELIMITER.check(&*format!("client:{}", client), limit) {
+                return false;
+            }
+        }
+        if let Some(ref limit) = user.author_rate_limit {
+            if !RATELIMITER.check(&*format!("author:{}:{}", client, data.author), limit) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /**
+     * Process a message received from a client
+     * @param auth_manager
+     * @param peer: the identity of the client if it sent a certificate
+     * @param pusher: where to push data for endpoints registered by the client (WebSocket)
+     * @param id: the id of the frame
+     * @param data_received: the body of the frame
+     * @return the reply to send to the client
+     */
+    fn process_data(auth_manager: &AuthManager,
+                    peer: Option<&PeerIdentity>,
+                    pusher: Option<&Pusher>,
+                    id: Option<String>,
+                    data_received: String)
+                    -> Reply {
+        info!(target:"server", "\n{}", data_received);
+        // Data can be sent in a signed envelope
+        let envelope = Envelope::from_json(&*data_received);
+        let payload = match envelope {
+            Some(ref envelope) => &*envelope.payload,
+            None => &*data_received,
+        };
+        let data_to_process: RoriData = match decode(payload) {
+            Ok(data) => data,
+            Err(e) => {
+                error!(target:"Server", "Malformed data received: {}", e);
+                return Reply::new(id, ReplyStatus::Malformed);
+            }
+        };
+        EVENTS.publish(|| {
+            Event::about("message_received", &data_to_process)
+                .with("signed", envelope.is_some())
+                .with("data", redact(&data_to_process))
+        });
+        let authorization = |status: ReplyStatus| {
+            EVENTS.publish(|| {
+                Event::about("authorization", &data_to_process).with("result", String::from(status.as_str()))
+            });
+            Reply::new(id.clone(), status)
+        };
+        let user = match envelope {
+            Some(ref envelope) => auth_manager.authenticate_envelope(envelope, &data_to_process),
+            None => auth_manager.authenticate(&data_to_process, peer),
+        };
+        let user = match user {
+            Some(user) => user,
+            None => {
+                error!(target:"Server", "Stream not authorized! Don't process.");
+                METRICS.auth_failed("unauthorized");
+                return authorization(ReplyStatus::Unauthorized);
+            }
+        };
+        if !user.can_send(&data_to_process) {
+            error!(target:"Server", "{} can't send {} for {}! Don't process.",
+                   data_to_process.client, data_to_process.datatype, data_to_process.author);
+            METRICS.auth_failed("forbidden");
+            return authorization(ReplyStatus::Forbidden);
+        }
+        METRICS.message_received(&*data_to_process.client, &*data_to_process.datatype);
+        if !Server::within_rate_limits(user, &data_to_process) {
+            warn!(target:"Server", "Too many messages from {} ({})! Don't process.",
+                  data_to_process.client, data_to_process.author);
+            return authorization(ReplyStatus::RateLimited);
+        }
+        authorization(ReplyStatus::Accepted);
+        if data_to_process.datatype == "register" {
+            let mut endpoint_manager = ENDPOINTMANAGER.lock().unwrap();
+            // Endpoints connected with a WebSocket receive data on this connection
+            let endpoint_id = match pusher {
+                Some(pusher) => endpoint_manager.register_pushed_endpoint(data_to_process, pusher.clone()),
+                None => endpoint_manager.register_endpoint(data_to_process),
+            };
+            if endpoint_id.is_none() {
+                return Reply::new(id, ReplyStatus::Malformed);
+            }
+            // The client needs the id to manage its endpoint
+            let mut reply = Reply::new(id, ReplyStatus::Accepted);
+            reply.endpoint_id = endpoint_id;
+            return reply;
+        }
+        if data_to_process.datatype == "unregister" {
+            let mut data_to_process = data_to_process;
+            // Endpoints of a WebSocket connection use the connection as address
+            if let Some(pusher) = pusher {
+                data_to_process.content = format!("websocket:{}", pusher.connection);
+            }
+            if ENDPOINTMANAGER.lock().unwrap().unregister(&data_to_process) == 0 {
+                return Reply::new(id, ReplyStatus::UnknownEndpoint);
+            }
+            return Reply::new(id, ReplyStatus::Accepted);
+        }
+        if data_to_process.datatype == "heartbeat" {
+            if ENDPOINTMANAGER.lock().unwrap().heartbeat(&data_to_process) == 0 {
+                return Reply::new(id, ReplyStatus::UnknownEndpoint);
+            }
+            return Reply::new(id, ReplyStatus::Accepted);
+        }
+        let mut module_manager = ModuleManager::new(data_to_process);
+        module_manager.set_api_scopes(&user.api_scopes());
+        let result = module_manager.process();
+        let status = if result.matched {
+            ReplyStatus::Accepted
+        } else {
+            ReplyStatus::NoModuleMatched
+        };
+        let mut reply = Reply::new(id, status);
+        reply.replies = result.replies;
+        reply
+    }
+
+    /**
+     * Process a message read from a client and send the reply back to its connection.
+     * Called by workers of the pool.
+     */
+    fn process_task(auth_manager: &RwLock<Arc<AuthManager>>, task: Task) {
+        // Each message uses the last loaded configuration
+        let auth_manager = auth_manager.read().unwrap().clone();
+        let reply = Server::process_data(&auth_manager,
+                                         task.peer.as_ref(),
+                                         task.pusher.as_ref(),
+                                         task.id,
+                                         task.body);
+        let _ = task.reply.send(reply);
+    }
+
+    /**
+     * Open a connection and read its messages. Called by the thread of the connection.
+     * @param pool: where messages are processed
+     * @param connection: the accepted connection
+     * @param id: the id of the connection in CONNECTIONS
+     * @param ssl: the context for TLS listeners
+     * @param max_frame_size: the maximum size of a message
+     */
+    fn serve(pool: &WorkerPool<Task>,
+             connection: Connection,
+             id: usize,
+             ssl: Option<SslContext>,
+             max_frame_size: usize) {
+        match connection.open(id, ssl, max_frame_size) {
+            Ok(Some(client)) => Server::read_client(pool, client),
+            Ok(None) => CONNECTIONS.remove(id),
+            Err(e) => {
+                CONNECTIONS.remove(id);
+                error!(target:"server", "Connection failed because {}", e);
+            }
+        }
+    }
+
+    /**
+     * Read messages from a client until it closes the connection, and give them to the pool.
+     * Called by the thread of the connection.
+     */
+    fn read_client(pool: &WorkerPool<Task>, mut client: Client) {
+        let pusher = client.pusher();
+        // A client can send several messages on the same connection
+        loop {
+            let frame = match client.read() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
                 Err(e) => {
-                    error!(target:"server", "Connection failed because {}", e);
+                    error!(target:"Server", "Can't read data from client: {}", e);
+                    match e {
+                        FrameError::Io(_) => {}
+                        _ => client.reply(&Reply::new(None, ReplyStatus::Malformed)),
+                    }
+                    break;
+                }
+            };
+            let (sender, receiver) = channel();
+            let task = Task {
+                peer: client.peer.clone(),
+                pusher: pusher.clone(),
+                id: frame.id.clone(),
+                body: frame.body,
+                reply: sender,
+            };
+            let reply = match pool.submit(task) {
+                Ok(()) => {
+                    match receiver.recv() {
+                        Ok(reply) => reply,
+                        // The task panicked
+                        Err(_) => break,
+                    }
+                }
+                Err(_) => {
+                    let stats = WORKERSTATS.snapshot();
+                    warn!(target:"Server", "Too many messages ({} waiting, {} rejected)",
+                          stats.queue_depth, stats.rejected);
+                    Reply::new(frame.id, ReplyStatus::Busy)
                 }
+            };
+            // Legacy clients (version 0) don't wait for a reply
+            if frame.version != 0 {
+                client.reply(&reply);
             }
         }
+    }
+}
+
+/**
+ * A route of the API which needs credentials with a scope.
+ * Clients authenticate with HTTP basic auth (name and secret of the authorize list) or with a
+ * token given to modules.
+ */
+struct Protected<H: Handler> {
+    auth_manager: Arc<RwLock<Arc<AuthManager>>>,
+    scope: ApiScope,
+    handler: H,
+}
 
-        drop(listener);
+impl<H: Handler> Protected<H> {
+    /**
+     * @return the client and its scopes if the request has valid credentials
+     */
+    fn credentials(&self, request: &Request) -> Option<(String, Vec<ApiScope>)> {
+        if let Some(&Authorization(Basic { ref username, ref password })) =
+            request.headers.get::<Authorization<Basic>>() {
+            let auth_manager = self.auth_manager.read().unwrap().clone();
+            let secret = password.clone().unwrap_or(String::from(""));
+            return auth_manager.authenticate_api(&*username, &*secret)
+                .map(|user| (username.clone(), user.api_scopes()));
+        }
+        if let Some(&Authorization(Bearer { ref token })) =
+            request.headers.get::<Authorization<Bearer>>() {
+            return APITOKENS.check(&*token);
+        }
+        None
     }
+}
+
+/**
+ * The client of a request checked by Protected, and its scopes
+ */
+struct Caller;
 
-    fn is_authorized(authorize: Vec<AuthorizedUser>, data: RoriData) -> bool {
-        let mut hasher = Sha256::new();
-        hasher.input_str(&*data.secret);
-        let secret = hasher.result_str();
-        for client in authorize {
-            if client.name.unwrap().to_lowercase() == data.client.to_lowercase() &&
-               secret.to_lowercase() == client.secret.unwrap().to_lowercase() {
-                return true;
+impl typemap::Key for Caller {
+    type Value = (String, Vec<ApiScope>);
+}
+
+impl<H: Handler> Handler for Protected<H> {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        match self.credentials(request) {
+            Some(caller) if self.scope.granted_by(&caller.1) => {
+                request.extensions.insert::<Caller>(caller);
+                self.handler.handle(request)
+            }
+            Some((client, _)) => {
+                warn!(target:"API", "{} can't use {}", client, request.url);
+                Ok(ApiError::new(status::Forbidden, "missing api scope").response())
+            }
+            None => {
+                let mut response = ApiError::new(status::Unauthorized, "missing or invalid credentials")
+                    .response();
+                response.headers.set_raw("WWW-Authenticate", vec![b"Basic realm=\"RORI\"".to_vec()]);
+                Ok(response)
             }
         }
-        false
     }
+}
+
+/**
+ * A route of the first version of the API, replaced by a /v2 route
+ */
+struct Deprecated<H: Handler> {
+    successor: &'static str,
+    handler: H,
+}
 
-    fn handle_client(&mut self, mut client: Client) {
-        let authorized_clients = self.authorize.clone();
-        thread::spawn(move || {
-            let data_received = client.read();
-            let end = data_received.find(0u8 as char);
-            let (data_received, _) = data_received.split_at(end.unwrap_or(data_received.len()));
-            info!(target:"server", "\n{}", data_received);
-            let data_to_process = RoriData::from_json(String::from(data_received));
-            let data_authorized = Server::is_authorized(authorized_clients,
-                                                        data_to_process.clone());
-            if data_authorized {
-                if data_to_process.datatype == "register" {
-                    ENDPOINTMANAGER.lock().unwrap().register_endpoint(data_to_process);
-                } else {
-                    let module_manager = ModuleManager::new(data_to_process);
-                    module_manager.process();
+impl<H: Handler> Handler for Deprecated<H> {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut response = self.handler.handle(request)?;
+        response.headers.set_raw("Deprecation", vec![b"true".to_vec()]);
+        response.headers.set_raw("Link",
+                                 vec![format!("<{}>; rel=\"successor-version\"", self.successor)
+                                          .into_bytes()]);
+        Ok(response)
+    }
+}
+
+/**
+ * Body of a GET /events response: events are written until the client leaves or RORI stops
+ */
+struct EventStream {
+    id: u64,
+    events: Receiver<String>,
+}
+
+impl WriteBody for EventStream {
+    fn write_body(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(b": connected\n\n")?;
+        output.flush()?;
+        let mut idle = 0;
+        while !SHUTDOWN.load(Ordering::SeqCst) {
+            match self.events.recv_timeout(Duration::from_secs(1)) {
+                Ok(event) => {
+                    idle = 0;
+                    write!(output, "data: {}\n\n", event)?;
                 }
-            } else {
-                error!(target:"Server", "Stream not authorized! Don't process.");
+                Err(RecvTimeoutError::Timeout) => {
+                    idle += 1;
+                    if idle < EVENTS_KEEP_ALIVE {
+                        continue;
+                    }
+                    // Also detects clients which are gone
+                    idle = 0;
+                    output.write_all(b": keep-alive\n\n")?;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
             }
-        });
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        EVENTS.unsubscribe(self.id);
+        EVENT_STREAMS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug)]
+struct ShuttingDown;
+
+impl fmt::Display for ShuttingDown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RORI is stopping")
+    }
+}
+
+impl Error for ShuttingDown {
+    fn description(&self) -> &str {
+        "RORI is stopping"
     }
 }
 
+/**
+ * Refuse new requests when RORI is stopping
+ */
+struct ShutdownGuard;
+
+impl BeforeMiddleware for ShutdownGuard {
+    fn before(&self, _: &mut Request) -> IronResult<()> {
+        if SHUTDOWN.load(Ordering::SeqCst) {
+            let error = ApiError::new(status::ServiceUnavailable, "RORI is stopping");
+            return Err(IronError { error: Box::new(ShuttingDown), response: error.response() });
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Count API requests being processed, so RORI can wait for them when stopping
+ */
+struct InFlight<H: Handler> {
+    handler: H,
+}
+
+/**
+ * Forget a request when it is answered, even if its handler panics
+ */
+struct InFlightGuard;
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        API_REQUESTS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
 
-#[derive(Clone, RustcDecodable, RustcEncodable, Default, PartialEq, Debug)]
-struct ConfigAPI {
-    pub api_ip: Option<String>,
-    pub api_port: Option<String>,
+impl<H: Handler> Handler for InFlight<H> {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        API_REQUESTS.fetch_add(1, Ordering::SeqCst);
+        let _guard = InFlightGuard;
+        self.handler.handle(request)
+    }
 }
 
 pub struct API {
-    address: String,
+    address: SocketAddr,
+    max_frame_size: usize,
+    auth_manager: Arc<RwLock<Arc<AuthManager>>>,
+    workers: Arc<Workers>,
 }
 
 impl API {
-    fn parse_config(data: String) -> String {
-        let params: ConfigAPI = decode(&data[..]).unwrap();
+    /**
+     * @param path: path of config_server.json. Panics if the configuration is invalid.
+     */
+    pub fn new<P: AsRef<Path>>(path: P) -> API {
+        let config = Config::read(path).unwrap_or_else(|e| panic!("Invalid configuration:\n{}", e));
+        API::from_config(&config)
+    }
+
+    pub fn from_config(config: &Config) -> API {
+        let auth_manager = Arc::new(RwLock::new(Arc::new(Server::build_auth_manager(config))));
+        // Without a Server, data sent to /ingest is processed by workers of the API
+        let pool = Server::build_pool(config, auth_manager.clone());
+        API {
+            address: config.api_address,
+            max_frame_size: config.max_frame_size,
+            auth_manager: auth_manager,
+            workers: Arc::new(Workers::new(Some(pool))),
+        }
+    }
 
-        format!("{}:{}",
-                &params.api_ip.unwrap_or(String::from("")),
-                &params.api_port.unwrap_or(String::from("")))
+    /**
+     * Authorize clients of the API like clients of a Server (and follow its reloads)
+     * @param auth_manager: the AuthManager of the Server
+     */
+    pub fn set_auth_manager(&mut self, auth_manager: Arc<RwLock<Arc<AuthManager>>>) {
+        self.auth_manager = auth_manager;
     }
 
-    pub fn new<P: AsRef<Path>>(config: P) -> API {
-        // Configure from file
-        let mut file = File::open(config)
-            .ok()
-            .expect("Config file not found");
-        let mut data = String::new();
-        file.read_to_string(&mut data)
-            .ok()
-            .expect("failed to read!");
-        let address = API::parse_config(data);
-        API { address: address }
+    /**
+     * Process data sent to /ingest with the workers of a Server
+     * @param workers: the worker pool of the Server
+     */
+    pub fn set_workers(&mut self, workers: Arc<Workers>) {
+        self.workers = workers;
     }
 
+    /**
+     * Start the API and block
+     */
     pub fn start(&mut self) {
+        // Dropping Listening waits for the server
+        let _listening = self.listen();
+    }
+
+    /**
+     * Start the API in background
+     * @return the listening server
+     */
+    pub fn listen(&mut self) -> Listening {
         let mut router = Router::new();
-        router.get("/help", API::help, "help");
-        router.get("/rm/:id", API::remove_client, "rm");
-        router.get("/client/:owner/:datatype", API::get_client, "client");
-        router.post("/send/:id", API::send_from_rori, "send");
-        router.post("/reprocess", API::reprocess, "reprocess");
-        router.get("/add_word/:category/:word",
-                   API::add_word_to_category,
-                   "add_word");
-        router.get("/rm_word/:category/:word",
-                   API::remove_word_from_category,
-                   "rm_word");
-        router.get("/is/:category/:word", API::is_word_in_category, "is_in");
-        Iron::new(router).http(&*self.address).unwrap();
+        let mut routes = Vec::new();
+        // Data sent to ingest contains its own credentials
+        let workers = self.workers.clone();
+        let max_frame_size = self.max_frame_size;
+        let ingest = move |request: &mut Request| API::ingest(request, &workers, max_frame_size);
+        self.route(&mut router, &mut routes, "help", API::help);
+        self.route(&mut router, &mut routes, "openapi", API::openapi);
+        self.route(&mut router, &mut routes, "v2_ingest", ingest.clone());
+        self.route(&mut router, &mut routes, "v2_list_endpoints", API::list_endpoints);
+        self.route(&mut router, &mut routes, "v2_delete_endpoint", API::delete_endpoint);
+        self.route(&mut router, &mut routes, "v2_send", API::send_to_endpoint);
+        self.route(&mut router, &mut routes, "v2_reprocess", API::reprocess_data);
+        self.route(&mut router, &mut routes, "v2_get_word", API::get_word);
+        self.route(&mut router, &mut routes, "v2_put_word", API::put_word);
+        self.route(&mut router, &mut routes, "v2_delete_word", API::delete_word);
+        self.route(&mut router, &mut routes, "v2_stats", API::get_stats);
+        self.route(&mut router, &mut routes, "v2_list_dead_letters", API::list_dead_letters);
+        self.route(&mut router, &mut routes, "v2_replay_dead_letter", API::replay_dead_letter);
+        self.route(&mut router, &mut routes, "v2_delete_dead_letter", API::delete_dead_letter);
+        self.route(&mut router, &mut routes, "healthz", API::healthz);
+        self.route(&mut router, &mut routes, "readyz", API::readyz);
+        self.route(&mut router, &mut routes, "metrics", API::metrics);
+        self.route(&mut router, &mut routes, "events", API::events);
+        // First version of the API
+        self.route(&mut router, &mut routes, "rm", API::remove_client);
+        self.route(&mut router, &mut routes, "client", API::get_client);
+        self.route(&mut router, &mut routes, "send", API::send_from_rori);
+        self.route(&mut router, &mut routes, "reprocess", API::reprocess);
+        self.route(&mut router, &mut routes, "ingest", ingest);
+        self.route(&mut router, &mut routes, "add_word", API::add_word_to_category);
+        self.route(&mut router, &mut routes, "rm_word", API::remove_word_from_category);
+        self.route(&mut router, &mut routes, "is_in", API::is_word_in_category);
+        self.route(&mut router, &mut routes, "stats", API::get_stats);
+        for route in openapi::ROUTES {
+            assert!(routes.contains(&route.id), "route {} has no handler", route.id);
+        }
+        let mut chain = Chain::new(router);
+        chain.link_before(ShutdownGuard);
+        let iron = Iron::new(InFlight { handler: chain });
+        MAX_EVENT_STREAMS.store(cmp::max(1, iron.threads / EVENT_STREAMS_SHARE), Ordering::SeqCst);
+        iron.http(self.address).unwrap()
+    }
+
+    /**
+     * Wait for API requests being processed (modules launched by /ingest or /reprocess). New
+     * requests are refused once RORI is stopping.
+     * @param timeout: how long to wait
+     * @return false if some requests are still processed after the timeout
+     */
+    pub fn drain(timeout: Duration) -> bool {
+        // None if the timeout is too large to be reached
+        let deadline = Instant::now().checked_add(timeout);
+        while API_REQUESTS.load(Ordering::SeqCst) > 0 {
+            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        true
+    }
+
+    /**
+     * Add a route of openapi::ROUTES to the router, checking credentials and telling if it's
+     * deprecated
+     * @param routes: the ids of the routes already added
+     * @param id: the id of the route
+     * @param handler: the route
+     */
+    fn route<H: Handler>(&self,
+                         router: &mut Router,
+                         routes: &mut Vec<&'static str>,
+                         id: &'static str,
+                         handler: H) {
+        let route = openapi::find(id).unwrap_or_else(|| panic!("route {} is not described", id));
+        let mut handler: Box<dyn Handler> = Box::new(handler);
+        if let Some(scope) = route.scope {
+            handler = Box::new(Protected {
+                auth_manager: self.auth_manager.clone(),
+                scope: scope,
+                handler: handler,
+            });
+        }
+        if let Some(successor) = route.successor {
+            handler = Box::new(Deprecated {
+                successor: successor,
+                handler: handler,
+            });
+        }
+        router.route(route.iron_method(), route.path, handler, id);
+        routes.push(id);
     }
 
     #[allow(unused_variables)]
     pub fn help(request: &mut Request) -> IronResult<Response> {
-        let help = "RORI API:
-        GET rm/:id => remove an endpoint
-        GET client/:owner/:datatype => get endpoint list
-        POST RoriData to send/:id => send data for client
-        POST RoriData to reprocess/ => reprocess this data and call modules
-        GET add_word/:category/:word => Add word to a category
-        GET rm_word/:category/:word => Remove word from category
-        GET is/:category/:word => Test if a word is in a category";
-        Ok(Response::with((status::Ok, help)))
+        Ok(Response::with((status::Ok, openapi::help())))
+    }
+
+    #[allow(unused_variables)]
+    pub fn openapi(request: &mut Request) -> IronResult<Response> {
+        Ok(json_response(status::Ok, openapi::document().to_string()))
     }
 
     pub fn remove_client(request: &mut Request) -> IronResult<Response> {
@@ -254,11 +1475,15 @@ impl API {
         }
         let datatype = request.extensions.get::<Router>().unwrap().find("datatype").unwrap_or("");
         if datatype == "" {
-            return Ok(Response::with((status::Ok, "No owner specified")));
+            return Ok(Response::with((status::Ok, "No datatype specified")));
         }
+        let liveness = match API::liveness(request) {
+            Ok(liveness) => liveness,
+            Err(error) => return Ok(Response::with((status::Ok, error.message))),
+        };
         let collected_endpoints = ENDPOINTMANAGER.lock()
             .unwrap()
-            .get_endpoint_for_type(String::from(datatype), String::from(owner));
+            .get_endpoint_for_type(String::from(datatype), String::from(owner), liveness);
 
         let encoded_result = json::encode(&collected_endpoints).unwrap();
         Ok(Response::with((status::Ok, encoded_result.to_string())))
@@ -272,9 +1497,11 @@ impl API {
         }
         let mut payload = String::from("");
         let _ = request.body.read_to_string(&mut payload);
-        ENDPOINTMANAGER.lock()
-            .unwrap()
-            .send_to_endpoint(id as u64, &payload);
+        let endpoint_manager = ENDPOINTMANAGER.lock().unwrap();
+        if endpoint_manager.get_endpoint(id as u64).is_none() {
+            return Ok(ApiError::new(status::NotFound, "unknown endpoint").response());
+        }
+        endpoint_manager.send_to_endpoint(id as u64, &payload);
         Ok(Response::with((status::Ok, payload)))
     }
 
@@ -282,11 +1509,359 @@ impl API {
         // Warning, will process and after return OK!
         let mut payload = String::from("");
         let _ = request.body.read_to_string(&mut payload);
-        let module_manager = ModuleManager::new(RoriData::from_json(String::from(payload)));
+        let mut module_manager = ModuleManager::new(RoriData::from_json(String::from(payload)));
+        module_manager.set_api_scopes(&API::caller_scopes(request));
         module_manager.process();
         Ok(Response::with((status::Ok, "")))
     }
 
+    /**
+     * Process a RoriData (or a signed envelope) like the Server does
+     * @return the reply as JSON
+     */
+    pub fn ingest(request: &mut Request, workers: &Workers, max_frame_size: usize)
+                  -> IronResult<Response> {
+        let mut payload = Vec::new();
+        let read = request.body.by_ref().take(max_frame_size as u64 + 1).read_to_end(&mut payload);
+        let reply = if read.is_err() || payload.len() > max_frame_size {
+            Reply::new(None, ReplyStatus::Malformed)
+        } else {
+            match String::from_utf8(payload) {
+                Ok(payload) => workers.process(None, payload),
+                Err(_) => Reply::new(None, ReplyStatus::Malformed),
+            }
+        };
+        let http_status = match reply.status {
+            ReplyStatus::Accepted | ReplyStatus::NoModuleMatched => status::Ok,
+            ReplyStatus::Unauthorized => status::Unauthorized,
+            ReplyStatus::Forbidden => status::Forbidden,
+            ReplyStatus::RateLimited => status::TooManyRequests,
+            ReplyStatus::Malformed => status::BadRequest,
+            ReplyStatus::Busy => status::ServiceUnavailable,
+            ReplyStatus::UnknownEndpoint => status::NotFound,
+        };
+        Ok(Response::with((http_status, Header(ContentType::json()), reply.to_json())))
+    }
+
+    /**
+     * GET /v2/endpoints?datatype=:datatype[&owner=:owner]
+     * @return endpoints for a datatype, for all owners if owner is missing
+     */
+    pub fn list_endpoints(request: &mut Request) -> IronResult<Response> {
+        let mut datatype = String::new();
+        let mut owner = String::new();
+        for (key, value) in request.url.as_ref().query_pairs() {
+            match &*key {
+                "datatype" => datatype = value.into_owned(),
+                "owner" => owner = value.into_owned(),
+                _ => {}
+            }
+        }
+        if datatype.len() == 0 {
+            return Ok(ApiError::new(status::BadRequest, "datatype is missing").response());
+        }
+        let liveness = match API::liveness(request) {
+            Ok(liveness) => liveness,
+            Err(error) => return Ok(error.response()),
+        };
+        let endpoints = ENDPOINTMANAGER.lock()
+            .unwrap()
+            .get_endpoint_for_type(datatype, owner, liveness);
+        Ok(json_response(status::Ok, json::encode(&endpoints).unwrap()))
+    }
+
+    /**
+     * DELETE /v2/endpoints/:id
+     */
+    pub fn delete_endpoint(request: &mut Request) -> IronResult<Response> {
+        let id = match API::endpoint_id(request) {
+            Ok(id) => id,
+            Err(error) => return Ok(error.response()),
+        };
+        if !ENDPOINTMANAGER.lock().unwrap().remove_endpoint(id) {
+            return Ok(ApiError::new(status::NotFound, "unknown endpoint").response());
+        }
+        Ok(Response::with(status::NoContent))
+    }
+
+    /**
+     * POST /v2/endpoints/:id/data
+     * The body is sent as is to the endpoint
+     */
+    pub fn send_to_endpoint(request: &mut Request) -> IronResult<Response> {
+        let id = match API::endpoint_id(request) {
+            Ok(id) => id,
+            Err(error) => return Ok(error.response()),
+        };
+        let mut payload = String::new();
+        if request.body.read_to_string(&mut payload).is_err() {
+            return Ok(ApiError::new(status::BadRequest, "the body is not UTF-8").response());
+        }
+        let endpoint_manager = ENDPOINTMANAGER.lock().unwrap();
+        if endpoint_manager.get_endpoint(id).is_none() {
+            return Ok(ApiError::new(status::NotFound, "unknown endpoint").response());
+        }
+        endpoint_manager.send_to_endpoint(id, &payload);
+        Ok(Response::with(status::Accepted))
+    }
+
+    /**
+     * GET /v2/dead-letters
+     * @return data which couldn't be sent to endpoints, as JSON
+     */
+    pub fn list_dead_letters(_: &mut Request) -> IronResult<Response> {
+        Ok(json_response(status::Ok, json::encode(&DELIVERIES.dead_letters()).unwrap()))
+    }
+
+    /**
+     * POST /v2/dead-letters/:id/replay
+     * The dead letter is queued again for its endpoint
+     */
+    pub fn replay_dead_letter(request: &mut Request) -> IronResult<Response> {
+        let id = match API::dead_letter_id(request) {
+            Ok(id) => id,
+            Err(error) => return Ok(error.response()),
+        };
+        let letter = match DELIVERIES.dead_letter(id) {
+            Some(letter) => letter,
+            None => return Ok(ApiError::new(status::NotFound, "unknown dead letter").response()),
+        };
+        if ENDPOINTMANAGER.lock().unwrap().get_endpoint(letter.endpoint).is_none() {
+            return Ok(ApiError::new(status::Conflict, "the endpoint is not registered anymore").response());
+        }
+        if !DELIVERIES.replay(id) {
+            return Ok(ApiError::new(status::NotFound, "unknown dead letter").response());
+        }
+        Ok(Response::with(status::Accepted))
+    }
+
+    /**
+     * DELETE /v2/dead-letters/:id
+     */
+    pub fn delete_dead_letter(request: &mut Request) -> IronResult<Response> {
+        let id = match API::dead_letter_id(request) {
+            Ok(id) => id,
+            Err(error) => return Ok(error.response()),
+        };
+        if DELIVERIES.remove_dead_letter(id).is_none() {
+            return Ok(ApiError::new(status::NotFound, "unknown dead letter").response());
+        }
+        Ok(Response::with(status::NoContent))
+    }
+
+    /**
+     * POST /v2/reprocess
+     * @return which modules were launched and what they replied, as JSON
+     */
+    pub fn reprocess_data(request: &mut Request) -> IronResult<Response> {
+        let mut payload = String::new();
+        let _ = request.body.read_to_string(&mut payload);
+        let data: RoriData = match decode(&*payload) {
+            Ok(data) => data,
+            Err(_) => return Ok(ApiError::new(status::BadRequest, "invalid RoriData").response()),
+        };
+        let mut module_manager = ModuleManager::new(data);
+        module_manager.set_api_scopes(&API::caller_scopes(request));
+        let result = module_manager.process();
+        let mut body = json::Object::new();
+        body.insert(String::from("matched"), json::Json::Boolean(result.matched));
+        body.insert(String::from("replies"),
+                    json::Json::Array(result.replies.into_iter().map(json::Json::String).collect()));
+        Ok(json_response(status::Ok, json::Json::Object(body).to_string()))
+    }
+
+    /**
+     * GET /v2/words/:category/:word
+     */
+    pub fn get_word(request: &mut Request) -> IronResult<Response> {
+        let (category, word) = API::word_params(request);
+        let wm = WordsManager::new(String::from("wordsclassification"));
+        if !wm.is_word_in_category(word.clone(), category.clone()) {
+            return Ok(ApiError::new(status::NotFound, "the word is not in this category")
+                .response());
+        }
+        Ok(json_response(status::Ok, API::word_json(category, word)))
+    }
+
+    /**
+     * PUT /v2/words/:category/:word
+     */
+    pub fn put_word(request: &mut Request) -> IronResult<Response> {
+        let (category, word) = API::word_params(request);
+        let mut wm = WordsManager::new(String::from("wordsclassification"));
+        if wm.is_word_in_category(word.clone(), category.clone()) {
+            return Ok(ApiError::new(status::Conflict, "the word is already in this category")
+                .response());
+        }
+        wm.add_word_to_category(word.clone(), category.clone());
+        Ok(json_response(status::Created, API::word_json(category, word)))
+    }
+
+    /**
+     * DELETE /v2/words/:category/:word
+     */
+    pub fn delete_word(request: &mut Request) -> IronResult<Response> {
+        let (category, word) = API::word_params(request);
+        let mut wm = WordsManager::new(String::from("wordsclassification"));
+        if !wm.is_word_in_category(word.clone(), category.clone()) {
+            return Ok(ApiError::new(status::NotFound, "the word is not in this category")
+                .response());
+        }
+        wm.remove_word_from_category(word, category);
+        Ok(Response::with(status::NoContent))
+    }
+
+    /**
+     * @return the scopes of the client which sent the request
+     */
+    fn caller_scopes(request: &Request) -> Vec<ApiScope> {
+        request.extensions.get::<Caller>().map(|caller| caller.1.clone()).unwrap_or(Vec::new())
+    }
+
+    /**
+     * @return the :id of the route, or a 400 error if it isn't a number
+     */
+    fn endpoint_id(request: &Request) -> Result<u64, ApiError> {
+        let id = request.extensions.get::<Router>().unwrap().find("id").unwrap_or("");
+        id.parse::<u64>().map_err(|_| ApiError::new(status::BadRequest, "invalid endpoint id"))
+    }
+
+    /**
+     * @return the :id of a dead letters route, or a 400 error if it isn't a number
+     */
+    fn dead_letter_id(request: &Request) -> Result<u64, ApiError> {
+        let id = request.extensions.get::<Router>().unwrap().find("id").unwrap_or("");
+        id.parse::<u64>().map_err(|_| ApiError::new(status::BadRequest, "invalid dead letter id"))
+    }
+
+    /**
+     * @return the ?status= of the request (any if missing), or a 400 error if it is unknown
+     */
+    fn liveness(request: &Request) -> Result<Liveness, ApiError> {
+        let status = request.url
+            .as_ref()
+            .query_pairs()
+            .find(|&(ref key, _)| key == "status")
+            .map(|(_, value)| value.into_owned());
+        match status {
+            Some(status) => {
+                Liveness::from_str(&*status).ok_or_else(|| {
+                    ApiError::new(status::BadRequest, "status must be any, online or offline")
+                })
+            }
+            None => Ok(Liveness::Any),
+        }
+    }
+
+    /**
+     * @return the :category and :word of the route
+     */
+    fn word_params(request: &Request) -> (String, String) {
+        let params = request.extensions.get::<Router>().unwrap();
+        (String::from(params.find("category").unwrap_or("")),
+         String::from(params.find("word").unwrap_or("")))
+    }
+
+    fn word_json(category: String, word: String) -> String {
+        let mut body = BTreeMap::new();
+        body.insert("category", category);
+        body.insert("word", word);
+        json::encode(&body).unwrap()
+    }
+
+    #[allow(unused_variables)]
+    pub fn get_stats(request: &mut Request) -> IronResult<Response> {
+        let workers = WORKERSTATS.snapshot();
+        let mut stats = BTreeMap::new();
+        stats.insert("queue_depth", workers.queue_depth);
+        stats.insert("active", workers.active);
+        stats.insert("rejected", workers.rejected);
+        stats.insert("rate_limited", RATELIMITER.limited());
+        Ok(json_response(status::Ok, json::encode(&stats).unwrap()))
+    }
+
+    /**
+     * GET /healthz: answers while the API runs
+     */
+    #[allow(unused_variables)]
+    pub fn healthz(request: &mut Request) -> IronResult<Response> {
+        Ok(json_response(status::Ok, String::from("{\"status\":\"ok\"}")))
+    }
+
+    /**
+     * GET /readyz: check that the Server accepts connections and that modules and words can be
+     * read
+     */
+    #[allow(unused_variables)]
+    pub fn readyz(request: &mut Request) -> IronResult<Response> {
+        let mut checks = BTreeMap::new();
+        checks.insert("listeners", LISTENING.load(Ordering::SeqCst));
+        checks.insert("modules", Path::new("rori_modules").is_dir());
+        checks.insert("words", fs::File::open("wordsclassification").is_ok());
+        let ready = checks.values().all(|check| *check);
+        let mut body = json::Object::new();
+        body.insert(String::from("status"),
+                    json::Json::String(String::from(if ready { "ready" } else { "unavailable" })));
+        body.insert(String::from("checks"), json::Json::from_str(&*json::encode(&checks).unwrap()).unwrap());
+        let http_status = if ready {
+            status::Ok
+        } else {
+            status::ServiceUnavailable
+        };
+        Ok(json_response(http_status, json::Json::Object(body).to_string()))
+    }
+
+    /**
+     * GET /metrics: metrics in the Prometheus text format
+     */
+    #[allow(unused_variables)]
+    pub fn metrics(request: &mut Request) -> IronResult<Response> {
+        let gauges = Gauges {
+            registered_endpoints: ENDPOINTMANAGER.lock().unwrap().count(),
+            queue_depth: WORKERSTATS.snapshot().queue_depth,
+            pending_deliveries: DELIVERIES.pending(),
+            dead_letters: DELIVERIES.dead_letters().len(),
+        };
+        Ok(Response::with((status::Ok,
+                           Header(ContentType("text/plain; version=0.0.4".parse().unwrap())),
+                           METRICS.render(&gauges))))
+    }
+
+    /**
+     * GET /events?author=:author&client=:client&datatype=:datatype
+     * Stream events as Server-Sent Events, filtered by author, client and datatype
+     */
+    pub fn events(request: &mut Request) -> IronResult<Response> {
+        let mut filter = EventFilter::default();
+        for (key, value) in request.url.as_ref().query_pairs() {
+            match &*key {
+                "author" => filter.author = Some(value.into_owned()),
+                "client" => filter.client = Some(value.into_owned()),
+                "datatype" => filter.datatype = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+        let too_many = || {
+            EVENT_STREAMS.fetch_sub(1, Ordering::SeqCst);
+            Ok(ApiError::new(status::ServiceUnavailable, "too many event streams").response())
+        };
+        if EVENT_STREAMS.fetch_add(1, Ordering::SeqCst) >= MAX_EVENT_STREAMS.load(Ordering::SeqCst) {
+            return too_many();
+        }
+        let (id, events) = match EVENTS.subscribe(filter) {
+            Some(subscription) => subscription,
+            None => return too_many(),
+        };
+        let mut response = Response::with((status::Ok,
+                                           Header(ContentType("text/event-stream".parse().unwrap()))));
+        response.headers.set_raw("Cache-Control", vec![b"no-cache".to_vec()]);
+        response.body = Some(Box::new(EventStream {
+            id: id,
+            events: events,
+        }));
+        Ok(response)
+    }
+
     pub fn add_word_to_category(request: &mut Request) -> IronResult<Response> {
         let category = request.extensions.get::<Router>().unwrap().find("category").unwrap_or("");
         let word = request.extensions.get::<Router>().unwrap().find("word").unwrap_or("");
@@ -5,11 +5,15 @@ use rustc_serialize::json::decode;
 use rustc_serialize::json::Json;
 use std::fs::File;
 use std::io::prelude::*;
+use core::api_auth::{ApiScope, DEFAULT_TOKEN_TTL, module_scopes};
 use core::words_manager::WordsManager;
+use core::{APITOKENS, EVENTS, METRICS};
+use core::events::Event;
 
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 #[derive(Clone, RustcDecodable, RustcEncodable, Default, PartialEq, Debug)]
 pub struct Module {
@@ -22,9 +26,22 @@ pub struct Module {
     pub priority: u64,
 }
 
+/**
+ * What modules did with a RoriData
+ */
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct ProcessResult {
+    /// true if at least one module was launched
+    pub matched: bool,
+    /// replies returned by modules
+    pub replies: Vec<String>,
+}
+
 #[allow(dead_code)]
 pub struct ModuleManager {
     data: RoriData,
+    /// scopes of the api_token given to modules
+    api_scopes: Vec<ApiScope>,
 }
 
 /**
@@ -33,7 +50,19 @@ pub struct ModuleManager {
  */
 impl ModuleManager {
     pub fn new(data: RoriData) -> ModuleManager {
-        return ModuleManager { data: data };
+        return ModuleManager {
+            data: data,
+            api_scopes: Vec::new(),
+        };
+    }
+
+    /**
+     * Let modules call the API with the scopes of the client, without admin. Modules can't use
+     * the API by default.
+     * @param scopes: the scopes of the client which sent the data
+     */
+    pub fn set_api_scopes(&mut self, scopes: &[ApiScope]) {
+        self.api_scopes = module_scopes(scopes);
     }
 
     /**
@@ -72,91 +101,147 @@ impl ModuleManager {
 
     /**
      * Process a RORIData and executes modules
+     * @return which modules were launched and what they replied
      */
-    pub fn process(&self) {
+    pub fn process(&self) -> ProcessResult {
         // open modules/self.data.datatype.json
         let path = format!("rori_modules/{}.json", self.data.datatype);
-        let mut file = File::open(path)
-            .ok()
-            .expect("Can't find modules for this type");
         let mut modules = String::new();
-        file.read_to_string(&mut modules)
-            .ok()
-            .expect("failed to read module list");
+        let read = File::open(path).and_then(|mut file| file.read_to_string(&mut modules));
+        if read.is_err() {
+            warn!(target:"module_manager", "Can't find modules for {}", self.data.datatype);
+            return ProcessResult::default();
+        }
         // parse json
         let modules_list = Json::from_str(&*modules).unwrap();
+        let modules_list: Vec<Module> = modules_list.as_array()
+            .unwrap()
+            .iter()
+            .map(|item| decode(&*item.to_string()).unwrap())
+            .collect();
         // foreach priority, launch enabled modules if condition ok
         let mut priority = 0;
 
         let stop: AtomicBool = AtomicBool::new(false);
         let stop_arc: Arc<AtomicBool> = Arc::new(stop);
-        let module_found: AtomicBool = AtomicBool::new(false);
-        let module_found_arc: Arc<AtomicBool> = Arc::new(module_found);
+        let mut executed = false;
+        let replies_arc: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
         while !stop_arc.load(Ordering::Relaxed) {
             let mut children = vec![];
-            module_found_arc.store(false, Ordering::Relaxed);
-            for item in modules_list.as_array().unwrap() {
+            let mut module_found = false;
+            for module in modules_list.iter().filter(|module| module.priority == priority) {
+                module_found = true;
+                info!(target:"module_manager", "Module found: {}", module.name);
+                // Parse text module
+                if module.enabled && self.data.datatype == "text" {
+                    let final_regex = ModuleManager::transform_to_regex(module.condition.clone());
+                    let re = Regex::new(&*final_regex).unwrap();
+                    if !re.is_match(&*self.data.content.to_lowercase()) {
+                        info!(target:"module_manager", "{} condition don't match", module.name);
+                        continue;
+                    }
+                } else {
+                    if !module.enabled {
+                        warn!(target:"module_manager", "Unknown datatype: {}", self.data.datatype);
+                    }
+                    continue;
+                }
+                executed = true;
+                EVENTS.publish(|| {
+                    Event::about("module_matched", &self.data).with("module", module.name.clone())
+                });
                 let stop_arc_cloned: Arc<AtomicBool> = stop_arc.clone();
-                let module_found_arc_cloned: Arc<AtomicBool> = module_found_arc.clone();
+                let replies_arc_cloned: Arc<Mutex<Vec<String>>> = replies_arc.clone();
                 let data_cloned = self.data.clone();
-                let item_cloned = item.clone();
+                let api_scopes = self.api_scopes.clone();
+                let module = module.clone();
 
-                // Each modules are tested in a new thread.
-                children.push(thread::spawn(move || {
-                    let module: Module = decode(&*item_cloned.to_string()).unwrap();
-                    if module.priority == priority {
-                        module_found_arc_cloned.store(true, Ordering::Relaxed);
-                        info!(target:"module_manager", "Module found: {}", module.name);
-                        // Parse text module
-                        if module.enabled && data_cloned.datatype == "text" {
-                            let final_regex = ModuleManager::transform_to_regex(module.condition);
-                            let re = Regex::new(&*final_regex).unwrap();
-                            if re.is_match(&*data_cloned.content.to_lowercase()) {
-                                info!(target:"module_manager", "{} match! Launch module...", module.name);
-                                let continue_processing =
-                                    ModuleManager::exec_module(module.path, data_cloned.clone());
-                                info!(target:"module_manager", "{}: continue_processing: {}", module.name, continue_processing);
-                                if !continue_processing {
-                                    stop_arc_cloned.store(true, Ordering::Relaxed);
-                                    return;
-                                }
-                            } else {
-                                info!(target:"module_manager", "{} condition don't match", module.name);
-                            }
-                        } else if !module.enabled {
-                            warn!(target:"module_manager", "Unknown datatype: {}", data_cloned.datatype);
-                        }
+                // Only matching modules are launched, each in a new thread.
+                let name = module.name.clone();
+                children.push((name, thread::spawn(move || {
+                    info!(target:"module_manager", "{} match! Launch module...", module.name);
+                    EVENTS.publish(|| {
+                        Event::about("module_executed", &data_cloned)
+                            .with("module", module.name.clone())
+                    });
+                    let start = Instant::now();
+                    let (continue_processing, reply) =
+                        ModuleManager::exec_module(module.path.clone(),
+                                                   data_cloned.clone(),
+                                                   api_scopes);
+                    let duration = start.elapsed();
+                    METRICS.module_executed(&*module.name, duration);
+                    EVENTS.publish(|| {
+                        let milliseconds = duration.as_secs() * 1000 +
+                                           (duration.subsec_nanos() / 1_000_000) as u64;
+                        Event::about("module_finished", &data_cloned)
+                            .with("module", module.name.clone())
+                            .with("continue", continue_processing)
+                            .with("duration_ms", milliseconds)
+                            .with("reply", reply.clone())
+                    });
+                    if let Some(reply) = reply {
+                        replies_arc_cloned.lock().unwrap().push(reply);
                     }
-                }));
+                    info!(target:"module_manager", "{}: continue_processing: {}", module.name, continue_processing);
+                    if !continue_processing {
+                        stop_arc_cloned.store(true, Ordering::Relaxed);
+                    }
+                })));
             }
 
-            for child in children {
-                // Wait for the thread to finish. Returns a result.
-                let _ = child.join();
+            for (name, child) in children {
+                // Wait for the thread to finish. A module which panics failed.
+                if child.join().is_err() {
+                    error!(target:"module_manager", "{} failed", name);
+                    EVENTS.publish(|| Event::about("module_failed", &self.data).with("module", name));
+                }
             }
 
-            if !module_found_arc.load(Ordering::Relaxed) {
+            if !module_found {
                 break;
             }
             priority += 1;
         }
+
+        if !executed {
+            EVENTS.publish(|| Event::about("no_module_matched", &self.data));
+        }
+        let replies = replies_arc.lock().unwrap().clone();
+        ProcessResult {
+            matched: executed,
+            replies: replies,
+        }
     }
 
     /**
      * Execute a module
      * @param module: the path of the module to execute
      * @param roridata: the data to process (received from a client)
-     * @return if we should continue processing this data
+     * @param api_scopes: the scopes of the api_token
+     * @return if we should continue processing this data and the reply of the module if any.
+     * A module returns a bool or a (bool, str) tuple.
+     * The module gets an api_token to call the API for the client of the data.
      */
-    fn exec_module(module: String, roridata: RoriData) -> bool {
+    fn exec_module(module: String, roridata: RoriData, api_scopes: Vec<ApiScope>)
+                   -> (bool, Option<String>) {
+        let token = APITOKENS.issue(&*roridata.client,
+                                    api_scopes,
+                                    Duration::from_secs(DEFAULT_TOKEN_TTL));
+        let mut data = Json::from_str(&*roridata.to_string()).unwrap_or(Json::Null);
+        if let Some(object) = data.as_object_mut() {
+            object.insert(String::from("api_token"), Json::String(token.clone()));
+        }
         let py = Python::acquire_gil();
         let py = py.python();
         let load_module = py.import("rori_modules.load_module").unwrap();
-        let continue_processing: bool =
-            load_module.call(py, "exec_module", (module, roridata.to_string()), None)
-                .unwrap()
-                .extract(py)
-                .unwrap();
-        continue_processing
+        let result = load_module.call(py, "exec_module", (module, data.to_string()), None);
+        APITOKENS.revoke(&*token);
+        let result = result.unwrap();
+        if let Ok(continue_processing) = result.extract::<bool>(py) {
+            return (continue_processing, None);
+        }
+        let (continue_processing, reply): (bool, String) = result.extract(py).unwrap();
+        (continue_processing, Some(reply))
     }
 }
@@ -0,0 +1,730 @@
+use core::api_auth::ApiScope;
+use iron::method::Method;
+use rustc_serialize::json::{Json, Object};
+
+/**
+ * What a request or a response contains
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Schema {
+    /// plain text
+    Text,
+    /// Server-Sent Events
+    EventStream,
+    /// any JSON document
+    Json,
+    RoriData,
+    Endpoints,
+    Reply,
+    ProcessResult,
+    Stats,
+    Word,
+    Health,
+    Readiness,
+    DeadLetters,
+    Error,
+}
+
+impl Schema {
+    fn content(&self) -> Json {
+        let (content_type, schema) = match *self {
+            Schema::Text => ("text/plain", object(vec![("type", string("string"))])),
+            Schema::EventStream => ("text/event-stream", object(vec![("type", string("string"))])),
+            Schema::Json => ("application/json", object(vec![("type", string("object"))])),
+            _ => {
+                let name = format!("{:?}", self);
+                ("application/json", object(vec![("$ref", string(&*format!("#/components/schemas/{}", name)))]))
+            }
+        };
+        object(vec![(content_type, object(vec![("schema", schema)]))])
+    }
+}
+
+/**
+ * A possible response of a route
+ */
+pub struct ApiResponse {
+    pub status: u16,
+    pub description: &'static str,
+    pub schema: Option<Schema>,
+}
+
+/**
+ * A query parameter of a route
+ */
+pub struct Param {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub required: bool,
+}
+
+/**
+ * A route of the API. The router is built from ROUTES, so the OpenAPI document and the help
+ * can't drift from what is served.
+ */
+pub struct Route {
+    /// the id of the route in the router
+    pub id: &'static str,
+    pub method: &'static str,
+    /// the path, with :parameters
+    pub path: &'static str,
+    pub summary: &'static str,
+    /// the scope needed to use the route (None for public routes)
+    pub scope: Option<ApiScope>,
+    pub query: &'static [Param],
+    pub body: Option<Schema>,
+    pub responses: &'static [ApiResponse],
+    /// the route replacing this one, if deprecated
+    pub successor: Option<&'static str>,
+}
+
+const REPLY_RESPONSES: &'static [ApiResponse] = &[
+    ApiResponse { status: 200, description: "accepted or no_module_matched", schema: Some(Schema::Reply) },
+    ApiResponse { status: 400, description: "malformed", schema: Some(Schema::Reply) },
+    ApiResponse { status: 401, description: "unauthorized", schema: Some(Schema::Reply) },
+    ApiResponse { status: 403, description: "forbidden", schema: Some(Schema::Reply) },
+    ApiResponse { status: 404, description: "unknown_endpoint", schema: Some(Schema::Reply) },
+    ApiResponse { status: 429, description: "rate_limited", schema: Some(Schema::Reply) },
+    ApiResponse { status: 503, description: "busy", schema: Some(Schema::Reply) },
+];
+
+const TEXT_RESPONSE: &'static [ApiResponse] = &[
+    ApiResponse { status: 200, description: "OK", schema: Some(Schema::Text) },
+];
+
+const STATUS_PARAM: Param = Param {
+    name: "status",
+    description: "any (default), online or offline",
+    required: false,
+};
+
+const WORD_PATH: &'static str = "/v2/words/:category/:word";
+
+pub const ROUTES: &'static [Route] = &[
+    Route {
+        id: "help",
+        method: "get",
+        path: "/help",
+        summary: "Describe the API",
+        scope: None,
+        query: &[],
+        body: None,
+        responses: TEXT_RESPONSE,
+        successor: None,
+    },
+    Route {
+        id: "openapi",
+        method: "get",
+        path: "/openapi.json",
+        summary: "Get the OpenAPI document of the API",
+        scope: None,
+        query: &[],
+        body: None,
+        responses: &[ApiResponse { status: 200, description: "OK", schema: Some(Schema::Json) }],
+        successor: None,
+    },
+    Route {
+        id: "v2_ingest",
+        method: "post",
+        path: "/v2/ingest",
+        summary: "Authorize and process a RoriData (or a signed envelope) like the server",
+        scope: None,
+        query: &[],
+        body: Some(Schema::RoriData),
+        responses: REPLY_RESPONSES,
+        successor: None,
+    },
+    Route {
+        id: "v2_list_endpoints",
+        method: "get",
+        path: "/v2/endpoints",
+        summary: "Get the endpoints for a datatype",
+        scope: Some(ApiScope::Read),
+        query: &[Param { name: "datatype", description: "the datatype", required: true },
+                 Param { name: "owner", description: "the owner (all if missing)", required: false },
+                 STATUS_PARAM],
+        body: None,
+        responses: &[
+            ApiResponse { status: 200, description: "OK", schema: Some(Schema::Endpoints) },
+            ApiResponse { status: 400, description: "datatype is missing or status is unknown",
+                          schema: Some(Schema::Error) },
+        ],
+        successor: None,
+    },
+    Route {
+        id: "v2_delete_endpoint",
+        method: "delete",
+        path: "/v2/endpoints/:id",
+        summary: "Remove an endpoint",
+        scope: Some(ApiScope::Admin),
+        query: &[],
+        body: None,
+        responses: &[
+            ApiResponse { status: 204, description: "removed", schema: None },
+            ApiResponse { status: 400, description: "invalid endpoint id", schema: Some(Schema::Error) },
+            ApiResponse { status: 404, description: "unknown endpoint", schema: Some(Schema::Error) },
+        ],
+        successor: None,
+    },
+    Route {
+        id: "v2_send",
+        method: "post",
+        path: "/v2/endpoints/:id/data",
+        summary: "Send the body to an endpoint",
+        scope: Some(ApiScope::Send),
+        query: &[],
+        body: Some(Schema::Text),
+        responses: &[
+            ApiResponse { status: 202, description: "queued", schema: None },
+            ApiResponse { status: 400, description: "invalid endpoint id", schema: Some(Schema::Error) },
+            ApiResponse { status: 404, description: "unknown endpoint", schema: Some(Schema::Error) },
+        ],
+        successor: None,
+    },
+    Route {
+        id: "v2_reprocess",
+        method: "post",
+        path: "/v2/reprocess",
+        summary: "Call modules for a RoriData",
+        scope: Some(ApiScope::Send),
+        query: &[],
+        body: Some(Schema::RoriData),
+        responses: &[
+            ApiResponse { status: 200, description: "OK", schema: Some(Schema::ProcessResult) },
+            ApiResponse { status: 400, description: "invalid RoriData", schema: Some(Schema::Error) },
+        ],
+        successor: None,
+    },
+    Route {
+        id: "v2_get_word",
+        method: "get",
+        path: WORD_PATH,
+        summary: "Test if a word is in a category",
+        scope: Some(ApiScope::Read),
+        query: &[],
+        body: None,
+        responses: &[
+            ApiResponse { status: 200, description: "OK", schema: Some(Schema::Word) },
+            ApiResponse { status: 404, description: "not in the category", schema: Some(Schema::Error) },
+        ],
+        successor: None,
+    },
+    Route {
+        id: "v2_put_word",
+        method: "put",
+        path: WORD_PATH,
+        summary: "Add a word to a category",
+        scope: Some(ApiScope::Words),
+        query: &[],
+        body: None,
+        responses: &[
+            ApiResponse { status: 201, description: "added", schema: Some(Schema::Word) },
+            ApiResponse { status: 409, description: "already in the category", schema: Some(Schema::Error) },
+        ],
+        successor: None,
+    },
+    Route {
+        id: "v2_delete_word",
+        method: "delete",
+        path: WORD_PATH,
+        summary: "Remove a word from a category",
+        scope: Some(ApiScope::Words),
+        query: &[],
+        body: None,
+        responses: &[
+            ApiResponse { status: 204, description: "removed", schema: None },
+            ApiResponse { status: 404, description: "not in the category", schema: Some(Schema::Error) },
+        ],
+        successor: None,
+    },
+    Route {
+        id: "v2_stats",
+        method: "get",
+        path: "/v2/stats",
+        summary: "Get the state of the worker pool and the rate limiter",
+        scope: Some(ApiScope::Read),
+        query: &[],
+        body: None,
+        responses: &[ApiResponse { status: 200, description: "OK", schema: Some(Schema::Stats) }],
+        successor: None,
+    },
+    Route {
+        id: "v2_list_dead_letters",
+        method: "get",
+        path: "/v2/dead-letters",
+        summary: "Get the data which couldn't be sent to endpoints",
+        scope: Some(ApiScope::Admin),
+        query: &[],
+        body: None,
+        responses: &[ApiResponse { status: 200, description: "OK", schema: Some(Schema::DeadLetters) }],
+        successor: None,
+    },
+    Route {
+        id: "v2_replay_dead_letter",
+        method: "post",
+        path: "/v2/dead-letters/:id/replay",
+        summary: "Send a dead letter again to its endpoint",
+        scope: Some(ApiScope::Admin),
+        query: &[],
+        body: None,
+        responses: &[
+            ApiResponse { status: 202, description: "queued", schema: None },
+            ApiResponse { status: 400, description: "invalid dead letter id", schema: Some(Schema::Error) },
+            ApiResponse { status: 404, description: "unknown dead letter", schema: Some(Schema::Error) },
+            ApiResponse { status: 409, description: "the endpoint is not registered anymore",
+                          schema: Some(Schema::Error) },
+        ],
+        successor: None,
+    },
+    Route {
+        id: "v2_delete_dead_letter",
+        method: "delete",
+        path: "/v2/dead-letters/:id",
+        summary: "Forget a dead letter",
+        scope: Some(ApiScope::Admin),
+        query: &[],
+        body: None,
+        responses: &[
+            ApiResponse { status: 204, description: "removed", schema: None },
+            ApiResponse { status: 400, description: "invalid dead letter id", schema: Some(Schema::Error) },
+            ApiResponse { status: 404, description: "unknown dead letter", schema: Some(Schema::Error) },
+        ],
+        successor: None,
+    },
+    Route {
+        id: "healthz",
+        method: "get",
+        path: "/healthz",
+        summary: "Check that the API runs",
+        scope: None,
+        query: &[],
+        body: None,
+        responses: &[ApiResponse { status: 200, description: "OK", schema: Some(Schema::Health) }],
+        successor: None,
+    },
+    Route {
+        id: "readyz",
+        method: "get",
+        path: "/readyz",
+        summary: "Check that the server accepts connections and that modules and words can be read",
+        scope: None,
+        query: &[],
+        body: None,
+        responses: &[
+            ApiResponse { status: 200, description: "ready", schema: Some(Schema::Readiness) },
+            ApiResponse { status: 503, description: "not ready", schema: Some(Schema::Readiness) },
+        ],
+        successor: None,
+    },
+    Route {
+        id: "metrics",
+        method: "get",
+        path: "/metrics",
+        summary: "Get metrics in the Prometheus text format",
+        scope: Some(ApiScope::Read),
+        query: &[],
+        body: None,
+        responses: TEXT_RESPONSE,
+        successor: None,
+    },
+    Route {
+        id: "events",
+        method: "get",
+        path: "/events",
+        summary: "Stream events (messages, authorizations, modules, endpoints and deliveries) as \
+                  Server-Sent Events. Each event is a JSON object with an event field.",
+        scope: Some(ApiScope::Read),
+        query: &[Param { name: "author", description: "only events for this author", required: false },
+                 Param { name: "client", description: "only events for this client", required: false },
+                 Param { name: "datatype", description: "only events for this datatype", required: false }],
+        body: None,
+        responses: &[
+            ApiResponse { status: 200, description: "OK", schema: Some(Schema::EventStream) },
+            ApiResponse { status: 503, description: "too many event streams", schema: Some(Schema::Error) },
+        ],
+        successor: None,
+    },
+    // First version of the API
+    Route {
+        id: "rm",
+        method: "get",
+        path: "/rm/:id",
+        summary: "Remove an endpoint",
+        scope: Some(ApiScope::Admin),
+        query: &[],
+        body: None,
+        responses: TEXT_RESPONSE,
+        successor: Some("/v2/endpoints/:id"),
+    },
+    Route {
+        id: "client",
+        method: "get",
+        path: "/client/:owner/:datatype",
+        summary: "Get the endpoints for a datatype (* for all owners)",
+        scope: Some(ApiScope::Read),
+        query: &[STATUS_PARAM],
+        body: None,
+        responses: &[ApiResponse { status: 200, description: "OK", schema: Some(Schema::Endpoints) }],
+        successor: Some("/v2/endpoints"),
+    },
+    Route {
+        id: "send",
+        method: "post",
+        path: "/send/:id",
+        summary: "Send the body to an endpoint",
+        scope: Some(ApiScope::Send),
+        query: &[],
+        body: Some(Schema::Text),
+        responses: &[
+            ApiResponse { status: 200, description: "OK", schema: Some(Schema::Text) },
+            ApiResponse { status: 404, description: "unknown endpoint", schema: Some(Schema::Error) },
+        ],
+        successor: Some("/v2/endpoints/:id/data"),
+    },
+    Route {
+        id: "reprocess",
+        method: "post",
+        path: "/reprocess",
+        summary: "Call modules for a RoriData",
+        scope: Some(ApiScope::Send),
+        query: &[],
+        body: Some(Schema::RoriData),
+        responses: TEXT_RESPONSE,
+        successor: Some("/v2/reprocess"),
+    },
+    Route {
+        id: "ingest",
+        method: "post",
+        path: "/ingest",
+        summary: "Authorize and process a RoriData (or a signed envelope) like the server",
+        scope: None,
+        query: &[],
+        body: Some(Schema::RoriData),
+        responses: REPLY_RESPONSES,
+        successor: Some("/v2/ingest"),
+    },
+    Route {
+        id: "add_word",
+        method: "get",
+        path: "/add_word/:category/:word",
+        summary: "Add a word to a category",
+        scope: Some(ApiScope::Words),
+        query: &[],
+        body: None,
+        responses: TEXT_RESPONSE,
+        successor: Some(WORD_PATH),
+    },
+    Route {
+        id: "rm_word",
+        method: "get",
+        path: "/rm_word/:category/:word",
+        summary: "Remove a word from a category",
+        scope: Some(ApiScope::Words),
+        query: &[],
+        body: None,
+        responses: TEXT_RESPONSE,
+        successor: Some(WORD_PATH),
+    },
+    Route {
+        id: "is_in",
+        method: "get",
+        path: "/is/:category/:word",
+        summary: "Test if a word is in a category (1 or 0)",
+        scope: Some(ApiScope::Read),
+        query: &[],
+        body: None,
+        responses: TEXT_RESPONSE,
+        successor: Some(WORD_PATH),
+    },
+    Route {
+        id: "stats",
+        method: "get",
+        path: "/stats",
+        summary: "Get the state of the worker pool and the rate limiter",
+        scope: Some(ApiScope::Read),
+        query: &[],
+        body: None,
+        responses: &[ApiResponse { status: 200, description: "OK", schema: Some(Schema::Stats) }],
+        successor: Some("/v2/stats"),
+    },
+];
+
+const SCHEMAS: &'static str = r##"{
+  "RoriData": {
+    "type": "object",
+    "required": ["author", "content", "client", "datatype", "secret"],
+    "properties": {
+      "author": {"type": "string"},
+      "content": {"type": "string"},
+      "client": {"type": "string"},
+      "datatype": {"type": "string"},
+      "secret": {"type": "string"}
+    }
+  },
+  "Endpoint": {
+    "type": "object",
+    "required": ["name", "id", "owner", "address", "compatible_data", "display_name", "tags",
+                 "protocol_version", "tls", "capabilities"],
+    "properties": {
+      "name": {"type": "string"},
+      "id": {"type": "integer", "format": "int64", "minimum": 0},
+      "owner": {"type": "string"},
+      "address": {"type": "string"},
+      "compatible_data": {"type": "array", "items": {"type": "string"}},
+      "display_name": {"type": "string", "nullable": true},
+      "tags": {"type": "array", "items": {"type": "string"}},
+      "protocol_version": {"type": "integer", "description": "0 for the legacy registration"},
+      "tls": {
+        "type": "object",
+        "properties": {
+          "server_name": {"type": "string", "nullable": true},
+          "fingerprint": {"type": "string", "nullable": true}
+        }
+      },
+      "capabilities": {"type": "object"}
+    }
+  },
+  "Endpoints": {
+    "type": "array",
+    "items": {"$ref": "#/components/schemas/Endpoint"}
+  },
+  "Reply": {
+    "type": "object",
+    "required": ["id", "status", "replies"],
+    "properties": {
+      "id": {"type": "string"},
+      "status": {
+        "type": "string",
+        "enum": ["accepted", "unauthorized", "forbidden", "rate_limited", "malformed",
+                 "no_module_matched", "busy", "unknown_endpoint"]
+      },
+      "replies": {"type": "array", "items": {"type": "string"}},
+      "endpoint_id": {"type": "integer", "nullable": true, "description": "the id of the registered endpoint"}
+    }
+  },
+  "ProcessResult": {
+    "type": "object",
+    "required": ["matched", "replies"],
+    "properties": {
+      "matched": {"type": "boolean"},
+      "replies": {"type": "array", "items": {"type": "string"}}
+    }
+  },
+  "Stats": {
+    "type": "object",
+    "properties": {
+      "queue_depth": {"type": "integer"},
+      "active": {"type": "integer"},
+      "rejected": {"type": "integer"},
+      "rate_limited": {"type": "integer"}
+    }
+  },
+  "Word": {
+    "type": "object",
+    "required": ["category", "word"],
+    "properties": {
+      "category": {"type": "string"},
+      "word": {"type": "string"}
+    }
+  },
+  "Health": {
+    "type": "object",
+    "required": ["status"],
+    "properties": {
+      "status": {"type": "string", "enum": ["ok"]}
+    }
+  },
+  "Readiness": {
+    "type": "object",
+    "required": ["status", "checks"],
+    "properties": {
+      "status": {"type": "string", "enum": ["ready", "unavailable"]},
+      "checks": {
+        "type": "object",
+        "properties": {
+          "listeners": {"type": "boolean"},
+          "modules": {"type": "boolean"},
+          "words": {"type": "boolean"}
+        }
+      }
+    }
+  },
+  "DeadLetter": {
+    "type": "object",
+    "required": ["id", "endpoint", "data", "attempts", "error", "time"],
+    "properties": {
+      "id": {"type": "integer", "format": "int64", "minimum": 0},
+      "endpoint": {"type": "integer", "format": "int64", "minimum": 0, "description": "the id of the endpoint"},
+      "data": {"type": "string"},
+      "attempts": {"type": "integer"},
+      "error": {"type": "string", "description": "why the last attempt failed"},
+      "time": {"type": "integer", "format": "int64", "description": "UNIX time"}
+    }
+  },
+  "DeadLetters": {
+    "type": "array",
+    "items": {"$ref": "#/components/schemas/DeadLetter"}
+  },
+  "Error": {
+    "type": "object",
+    "required": ["error"],
+    "properties": {
+      "error": {
+        "type": "object",
+        "required": ["status", "code", "message"],
+        "properties": {
+          "status": {"type": "integer"},
+          "code": {"type": "string"},
+          "message": {"type": "string"}
+        }
+      }
+    }
+  }
+}"##;
+
+fn string(value: &str) -> Json {
+    Json::String(String::from(value))
+}
+
+fn object(entries: Vec<(&str, Json)>) -> Json {
+    Json::Object(entries.into_iter().map(|(key, value)| (String::from(key), value)).collect())
+}
+
+fn response(description: &str, schema: Option<Schema>) -> Json {
+    let mut entries = vec![("description", string(description))];
+    if let Some(schema) = schema {
+        entries.push(("content", schema.content()));
+    }
+    object(entries)
+}
+
+impl Route {
+    pub fn iron_method(&self) -> Method {
+        match self.method {
+            "get" => Method::Get,
+            "post" => Method::Post,
+            "put" => Method::Put,
+            "delete" => Method::Delete,
+            method => Method::Extension(String::from(method)),
+        }
+    }
+
+    /**
+     * @return the path with {parameters}, like in OpenAPI documents
+     */
+    pub fn openapi_path(&self) -> String {
+        self.path
+            .split('/')
+            .map(|segment| if segment.starts_with(':') {
+                format!("{{{}}}", &segment[1..])
+            } else {
+                String::from(segment)
+            })
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+
+    fn operation(&self) -> Json {
+        let mut parameters = Vec::new();
+        for segment in self.path.split('/').filter(|segment| segment.starts_with(':')) {
+            let kind = if segment == ":id" { "integer" } else { "string" };
+            parameters.push(object(vec![("name", string(&segment[1..])),
+                                        ("in", string("path")),
+                                        ("required", Json::Boolean(true)),
+                                        ("schema", object(vec![("type", string(kind))]))]));
+        }
+        for param in self.query {
+            parameters.push(object(vec![("name", string(param.name)),
+                                        ("in", string("query")),
+                                        ("description", string(param.description)),
+                                        ("required", Json::Boolean(param.required)),
+                                        ("schema", object(vec![("type", string("string"))]))]));
+        }
+
+        let mut responses = Object::new();
+        for api_response in self.responses {
+            responses.insert(api_response.status.to_string(),
+                             response(api_response.description, api_response.schema));
+        }
+        let mut operation = vec![("operationId", string(self.id)),
+                                 ("summary", string(self.summary)),
+                                 ("parameters", Json::Array(parameters))];
+        if let Some(scope) = self.scope {
+            responses.insert(String::from("401"),
+                             response("missing or invalid credentials", Some(Schema::Error)));
+            responses.insert(String::from("403"), response("missing api scope", Some(Schema::Error)));
+            operation.push(("security",
+                            Json::Array(vec![object(vec![("basicAuth", Json::Array(vec![]))]),
+                                             object(vec![("bearerAuth", Json::Array(vec![]))])])));
+            operation.push(("x-rori-scope", string(scope.as_str())));
+        }
+        if !responses.contains_key("503") {
+            responses.insert(String::from("503"), response("RORI is stopping", Some(Schema::Error)));
+        }
+        operation.push(("responses", Json::Object(responses)));
+        if let Some(body) = self.body {
+            operation.push(("requestBody",
+                            object(vec![("required", Json::Boolean(true)), ("content", body.content())])));
+        }
+        if self.successor.is_some() {
+            operation.push(("deprecated", Json::Boolean(true)));
+        }
+        object(operation)
+    }
+}
+
+/**
+ * @param id: the id of a route
+ * @return the route with this id
+ */
+pub fn find(id: &str) -> Option<&'static Route> {
+    ROUTES.iter().find(|route| route.id == id)
+}
+
+/**
+ * @return the OpenAPI 3 document describing ROUTES
+ */
+pub fn document() -> Json {
+    let mut paths = Object::new();
+    for route in ROUTES {
+        let path = paths.entry(route.openapi_path()).or_insert_with(|| Json::Object(Object::new()));
+        if let Json::Object(ref mut operations) = *path {
+            operations.insert(String::from(route.method), route.operation());
+        }
+    }
+    let schemes = object(vec![("basicAuth",
+                               object(vec![("type", string("http")), ("scheme", string("basic"))])),
+                              ("bearerAuth",
+                               object(vec![("type", string("http")), ("scheme", string("bearer"))]))]);
+    object(vec![("openapi", string("3.0.0")),
+                ("info",
+                 object(vec![("title", string("RORI API")),
+                             ("version", string(env!("CARGO_PKG_VERSION")))])),
+                ("paths", Json::Object(paths)),
+                ("components",
+                 object(vec![("schemas", Json::from_str(SCHEMAS).unwrap()),
+                             ("securitySchemes", schemes)]))])
+}
+
+/**
+ * @return a text describing ROUTES
+ */
+pub fn help() -> String {
+    let mut help = String::from("RORI API (basic auth or Bearer token, except for public routes). \
+                                 See /openapi.json for details.\n");
+    let mut deprecated = String::from("\nDeprecated:\n");
+    for route in ROUTES {
+        let scope = route.scope.map(|scope| scope.as_str()).unwrap_or("public");
+        let line = format!("{} {} ({}) => {}\n",
+                           route.method.to_uppercase(),
+                           route.path,
+                           scope,
+                           route.summary);
+        match route.successor {
+            Some(successor) => deprecated.push_str(&*format!("{}    use {}\n", line, successor)),
+            None => help.push_str(&*line),
+        }
+    }
+    help.push_str(&*deprecated);
+    help
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/**
+ * Number of buckets kept before forgetting full ones
+ */
+const MAX_BUCKETS: usize = 10000;
+
+/**
+ * Number of buckets forgotten at once when there are still too many, so this is not done for
+ * each new key
+ */
+const EVICTED_BUCKETS: usize = 1000;
+
+/**
+ * A token bucket: `rate` messages per second, with bursts of `burst` messages
+ */
+#[derive(Clone, RustcDecodable, RustcEncodable, Default, PartialEq, Debug)]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_update: Instant,
+    /// the limit used by this bucket, to know when it is full again
+    limit: RateLimit,
+}
+
+impl Bucket {
+    /**
+     * @return the tokens of the bucket at now
+     */
+    fn tokens(&self, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.last_update);
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        (self.tokens + elapsed * self.limit.rate).min(self.limit.burst)
+    }
+}
+
+/**
+ * Limit how many messages a client or an author can send
+ */
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    limited: AtomicUsize,
+}
+
+impl RateLimiter {
+    pub fn new() -> RateLimiter {
+        RateLimiter {
+            buckets: Mutex::new(HashMap::new()),
+            limited: AtomicUsize::new(0),
+        }
+    }
+
+    /**
+     * Take a token from a bucket
+     * @param key: the bucket to use
+     * @param limit: the limit of this bucket
+     * @return false if the message must be rejected
+     */
+    pub fn check(&self, key: &str, limit: &RateLimit) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(key) {
+            // Forget full buckets, they are the same as new ones
+            buckets.retain(|_, bucket| bucket.tokens(now) < bucket.limit.burst);
+            // Then the least recently used ones. Authors are chosen by clients, the number of
+            // buckets must stay bounded.
+            if buckets.len() > MAX_BUCKETS - EVICTED_BUCKETS {
+                let mut by_age: Vec<(Instant, String)> = buckets.iter()
+                    .map(|(key, bucket)| (bucket.last_update, key.clone()))
+                    .collect();
+                by_age.sort();
+                let excess = buckets.len() - (MAX_BUCKETS - EVICTED_BUCKETS);
+                for (_, key) in &by_age[..excess] {
+                    buckets.remove(key);
+                }
+            }
+        }
+        let bucket = buckets.entry(String::from(key)).or_insert(Bucket {
+            tokens: limit.burst,
+            last_update: now,
+            limit: limit.clone(),
+        });
+        bucket.tokens = bucket.tokens(now);
+        bucket.last_update = now;
+        // The limit can change when the configuration is reloaded
+        bucket.limit = limit.clone();
+        if bucket.tokens >= 1. {
+            bucket.tokens -= 1.;
+            return true;
+        }
+        self.limited.fetch_add(1, Ordering::SeqCst);
+        false
+    }
+
+    /**
+     * @return how many buckets are kept
+     */
+    pub fn buckets(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+
+    /**
+     * @return how many messages were rejected
+     */
+    pub fn limited(&self) -> usize {
+        self.limited.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,101 @@
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+use rustc_serialize::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/**
+ * What happened to a RoriData sent by an entry point
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReplyStatus {
+    Accepted,
+    Unauthorized,
+    Forbidden,
+    RateLimited,
+    Malformed,
+    NoModuleMatched,
+    Busy,
+    /// a heartbeat for an endpoint which is not registered (or expired)
+    UnknownEndpoint,
+}
+
+impl ReplyStatus {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ReplyStatus::Accepted => "accepted",
+            ReplyStatus::Unauthorized => "unauthorized",
+            ReplyStatus::Forbidden => "forbidden",
+            ReplyStatus::RateLimited => "rate_limited",
+            ReplyStatus::Malformed => "malformed",
+            ReplyStatus::NoModuleMatched => "no_module_matched",
+            ReplyStatus::Busy => "busy",
+            ReplyStatus::UnknownEndpoint => "unknown_endpoint",
+        }
+    }
+
+    pub fn from_str(status: &str) -> Option<ReplyStatus> {
+        match status {
+            "accepted" => Some(ReplyStatus::Accepted),
+            "unauthorized" => Some(ReplyStatus::Unauthorized),
+            "forbidden" => Some(ReplyStatus::Forbidden),
+            "rate_limited" => Some(ReplyStatus::RateLimited),
+            "malformed" => Some(ReplyStatus::Malformed),
+            "no_module_matched" => Some(ReplyStatus::NoModuleMatched),
+            "busy" => Some(ReplyStatus::Busy),
+            "unknown_endpoint" => Some(ReplyStatus::UnknownEndpoint),
+            _ => None,
+        }
+    }
+}
+
+impl Encodable for ReplyStatus {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_str(self.as_str())
+    }
+}
+
+impl Decodable for ReplyStatus {
+    fn decode<D: Decoder>(d: &mut D) -> Result<ReplyStatus, D::Error> {
+        let status = d.read_str()?;
+        ReplyStatus::from_str(&*status)
+            .ok_or_else(|| d.error(&*format!("unknown status: {}", status)))
+    }
+}
+
+/**
+ * The answer sent back to an entry point for each frame it sends
+ */
+#[derive(Clone, RustcDecodable, RustcEncodable, PartialEq, Debug)]
+pub struct Reply {
+    pub id: String,
+    pub status: ReplyStatus,
+    pub replies: Vec<String>,
+    /// the id of the registered endpoint, for a register
+    pub endpoint_id: Option<u64>,
+}
+
+impl Reply {
+    /**
+     * @param id: the id of the frame we answer to. If None, a new one is generated
+     * @param status
+     * @return a Reply without module replies
+     */
+    pub fn new(id: Option<String>, status: ReplyStatus) -> Reply {
+        let id = id.unwrap_or_else(|| NEXT_ID.fetch_add(1, Ordering::SeqCst).to_string());
+        Reply {
+            id: id,
+            status: status,
+            replies: Vec::new(),
+            endpoint_id: None,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        json::encode(self).unwrap_or(String::from("{}"))
+    }
+
+    pub fn from_json(data: &str) -> Option<Reply> {
+        json::decode(data).ok()
+    }
+}
@@ -0,0 +1,50 @@
+use libc;
+use std::mem;
+use std::ptr;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+/**
+ * Signals handled by RORI
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Signal {
+    /// SIGINT or SIGTERM
+    Terminate,
+    /// SIGHUP
+    Reload,
+}
+
+/**
+ * Block SIGINT, SIGTERM and SIGHUP and receive them from a dedicated thread.
+ * Must be called before any other thread is spawned, so every thread inherits the mask.
+ * @return where signals are sent
+ */
+pub fn install() -> Receiver<Signal> {
+    let mut set: libc::sigset_t = unsafe { mem::zeroed() };
+    unsafe {
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        libc::sigaddset(&mut set, libc::SIGTERM);
+        libc::sigaddset(&mut set, libc::SIGHUP);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut());
+    }
+    let (sender, receiver) = channel();
+    thread::spawn(move || loop {
+        let mut signal: libc::c_int = 0;
+        if unsafe { libc::sigwait(&set, &mut signal) } != 0 {
+            error!(target:"signals", "sigwait failed");
+            break;
+        }
+        let signal = match signal {
+            libc::SIGINT | libc::SIGTERM => Signal::Terminate,
+            libc::SIGHUP => Signal::Reload,
+            _ => continue,
+        };
+        info!(target:"signals", "Received {:?}", signal);
+        if sender.send(signal).is_err() {
+            break;
+        }
+    });
+    receiver
+}
@@ -0,0 +1,290 @@
+use core::{METRICS, SHUTDOWN};
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use reqwest;
+use rustc_serialize::hex::ToHex;
+use rustc_serialize::json::Json;
+use std::cmp;
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, TrySendError, sync_channel};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/**
+ * Events which can be sent to webhooks
+ */
+pub const WEBHOOK_EVENTS: &'static [&'static str] = &["endpoint_registered",
+                                                      "endpoint_removed",
+                                                      "endpoint_offline",
+                                                      "module_failed",
+                                                      "no_module_matched",
+                                                      "delivery_failed"];
+
+/**
+ * Default number of retries when a webhook fails
+ */
+pub const DEFAULT_WEBHOOK_RETRIES: u64 = 5;
+/**
+ * Maximum number of retries allowed in the configuration
+ */
+pub const MAX_WEBHOOK_RETRIES: u64 = 50;
+
+/**
+ * Delay before the first retry (in seconds). Doubled after each failure.
+ */
+const FIRST_RETRY_DELAY: u64 = 1;
+const MAX_RETRY_DELAY: u64 = 300;
+
+/**
+ * Timeout of a webhook call (in seconds)
+ */
+const WEBHOOK_TIMEOUT: u64 = 5;
+
+/**
+ * Maximum number of events waiting for each webhook
+ */
+const MAX_PENDING_EVENTS: usize = 1000;
+
+/**
+ * An URL called with a POST when some events happen
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct Webhook {
+    pub url: String,
+    /// used to sign calls
+    pub secret: String,
+    /// events sent to this webhook
+    pub events: Vec<String>,
+}
+
+/**
+ * @return the hex encoded HMAC-SHA256 of the body with the secret of the webhook
+ */
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut hmac = Hmac::new(Sha256::new(), secret.as_bytes());
+    hmac.input(body.as_bytes());
+    hmac.result().code().to_hex()
+}
+
+/**
+ * An event waiting to be sent to a webhook
+ */
+struct Delivery {
+    event: String,
+    body: String,
+    attempt: u64,
+    next_try: Instant,
+}
+
+/**
+ * Send events to webhooks, retrying failed calls with an exponential backoff
+ */
+pub struct WebhookDispatcher {
+    webhooks: Vec<Webhook>,
+    retries: u64,
+}
+
+impl WebhookDispatcher {
+    pub fn new(webhooks: Vec<Webhook>, retries: u64) -> WebhookDispatcher {
+        WebhookDispatcher {
+            webhooks: webhooks,
+            retries: retries,
+        }
+    }
+
+    /**
+     * Send events in new threads, until RORI stops or events are closed and sent. Each webhook
+     * has its own thread, so a webhook which is down doesn't delay the others.
+     * @param events: events of an EventBus, as JSON
+     */
+    pub fn spawn(self, events: Receiver<String>) -> JoinHandle<()> {
+        let retries = self.retries;
+        thread::spawn(move || {
+            let queues: Vec<_> = self.webhooks
+                .into_iter()
+                .map(|webhook| {
+                    let (sender, receiver) = sync_channel(MAX_PENDING_EVENTS);
+                    let events = webhook.events.clone();
+                    (events, sender, WebhookQueue::new(webhook, retries).spawn(receiver))
+                })
+                .collect();
+            while !SHUTDOWN.load(Ordering::SeqCst) {
+                let event = match events.recv_timeout(Duration::from_secs(1)) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                let kind = match Json::from_str(&*event) {
+                    Ok(json) => json.find("event").and_then(|kind| kind.as_string()).map(String::from),
+                    Err(_) => None,
+                };
+                let kind = match kind {
+                    Some(kind) => kind,
+                    None => continue,
+                };
+                for (kinds, sender, _) in &queues {
+                    if kinds.contains(&kind) {
+                        if let Err(TrySendError::Full(_)) =
+                               sender.try_send((kind.clone(), event.clone())) {
+                            METRICS.event_dropped();
+                        }
+                    }
+                }
+            }
+            // Let each webhook send its pending events
+            for (_, sender, handle) in queues {
+                drop(sender);
+                let _ = handle.join();
+            }
+        })
+    }
+}
+
+/**
+ * The events waiting for a webhook. The oldest ones are dropped when there are too many.
+ */
+struct WebhookQueue {
+    webhook: Webhook,
+    retries: u64,
+    pending: VecDeque<Delivery>,
+    dropped: usize,
+}
+
+impl WebhookQueue {
+    fn new(webhook: Webhook, retries: u64) -> WebhookQueue {
+        WebhookQueue {
+            webhook: webhook,
+            retries: retries,
+            pending: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    /**
+     * Call the webhook in a new thread, until RORI stops or events are closed and sent
+     * @param events: the kind and the JSON of events for this webhook
+     */
+    fn spawn(mut self, events: Receiver<(String, String)>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let mut closed = false;
+            while !SHUTDOWN.load(Ordering::SeqCst) {
+                let timeout = self.next_timeout();
+                if closed {
+                    if self.pending.len() == 0 {
+                        break;
+                    }
+                    thread::sleep(timeout);
+                } else {
+                    match events.recv_timeout(timeout) {
+                        Ok((event, body)) => self.queue(event, body),
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => closed = true,
+                    }
+                    // Events received while the webhook was called
+                    while let Ok((event, body)) = events.try_recv() {
+                        self.queue(event, body);
+                    }
+                }
+                self.deliver();
+            }
+            if self.pending.len() > 0 {
+                warn!(target:"Webhooks", "{} events are not sent to {}",
+                      self.pending.len(), self.webhook.url);
+            }
+        })
+    }
+
+    /**
+     * @return how long to wait for the next event
+     */
+    fn next_timeout(&self) -> Duration {
+        let now = Instant::now();
+        let next_try = self.pending.iter().map(|delivery| delivery.next_try).min();
+        match next_try {
+            Some(next_try) if next_try <= now => Duration::from_millis(0),
+            Some(next_try) => cmp::min(next_try - now, Duration::from_secs(1)),
+            None => Duration::from_secs(1),
+        }
+    }
+
+    /**
+     * Prepare the call of the webhook for an event
+     */
+    fn queue(&mut self, event: String, body: String) {
+        if self.pending.len() >= MAX_PENDING_EVENTS {
+            self.pending.pop_front();
+            METRICS.event_dropped();
+            if self.dropped == 0 {
+                warn!(target:"Webhooks", "Too many events for {}, dropping the oldest ones",
+                      self.webhook.url);
+            }
+            self.dropped += 1;
+        }
+        self.pending.push_back(Delivery {
+            event: event,
+            body: body,
+            attempt: 0,
+            next_try: Instant::now(),
+        });
+    }
+
+    /**
+     * Call the webhook for events which are due
+     */
+    fn deliver(&mut self) {
+        let now = Instant::now();
+        let (due, waiting): (VecDeque<Delivery>, VecDeque<Delivery>) =
+            self.pending.drain(..).partition(|delivery| delivery.next_try <= now);
+        self.pending = waiting;
+        for mut delivery in due {
+            match WebhookQueue::call(&self.webhook, &delivery) {
+                Ok(_) => {
+                    info!(target:"Webhooks", "{} sent to {}", delivery.event, self.webhook.url);
+                    if self.dropped > 0 {
+                        info!(target:"Webhooks", "{} is back, {} events were dropped",
+                              self.webhook.url, self.dropped);
+                        self.dropped = 0;
+                    }
+                }
+                Err(e) => {
+                    delivery.attempt += 1;
+                    if delivery.attempt > self.retries {
+                        error!(target:"Webhooks", "Can't send {} to {}: {}. Give up.",
+                               delivery.event, self.webhook.url, e);
+                        continue;
+                    }
+                    let delay = cmp::min(FIRST_RETRY_DELAY << cmp::min(delivery.attempt - 1, 16),
+                                         MAX_RETRY_DELAY);
+                    warn!(target:"Webhooks", "Can't send {} to {}: {}. Retry in {}s",
+                          delivery.event, self.webhook.url, e, delay);
+                    delivery.next_try = Instant::now() + Duration::from_secs(delay);
+                    self.pending.push_back(delivery);
+                }
+            }
+        }
+    }
+
+    /**
+     * POST an event to a webhook
+     * @return an error if the webhook doesn't answer with a 2xx status
+     */
+    fn call(webhook: &Webhook, delivery: &Delivery) -> Result<(), String> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(WEBHOOK_TIMEOUT))
+            .build()
+            .map_err(|e| e.to_string())?;
+        let response = client.post(&*webhook.url)
+            .header("Content-Type", "application/json")
+            .header("X-Rori-Event", &*delivery.event)
+            .header("X-Rori-Signature", &*format!("sha256={}", sign(&*webhook.secret, &*delivery.body)))
+            .body(delivery.body.clone())
+            .send()
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("status {}", response.status()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+use core::framing::FrameError;
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use rustc_serialize::base64::{STANDARD, ToBase64};
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+
+const GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const MAX_HANDSHAKE_SIZE: usize = 8192;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+/**
+ * Maximum size of the payload of a control frame (close, ping, pong)
+ */
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+/**
+ * A message received from a WebSocket client
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    /// The client closed the connection
+    Close,
+}
+
+/**
+ * Compute the Sec-WebSocket-Accept header for a Sec-WebSocket-Key
+ */
+pub fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.input_str(&*format!("{}{}", key.trim(), GUID));
+    let mut hash = [0; 20];
+    hasher.result(&mut hash);
+    hash.to_base64(STANDARD)
+}
+
+/**
+ * Server side of a WebSocket (RFC 6455).
+ * The opening handshake is done by the first poll. Bytes are buffered, so the stream can have a
+ * read timeout: poll returns None when nothing is received in time, and can be called again.
+ */
+pub struct WebSocket<S: Read + Write> {
+    stream: S,
+    buffer: Vec<u8>,
+    max_message_size: usize,
+    open: bool,
+    fragments: Option<(u8, Vec<u8>)>,
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    /**
+     * @param stream: the stream of the client, before the handshake
+     * @param max_message_size: the maximum size of a message
+     * @return a WebSocket
+     */
+    pub fn new(stream: S, max_message_size: usize) -> WebSocket<S> {
+        WebSocket {
+            stream: stream,
+            buffer: Vec::new(),
+            max_message_size: max_message_size,
+            open: false,
+            fragments: None,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /**
+     * Read the next message. Pings are answered.
+     * @return the message, or None if the read timed out
+     */
+    pub fn poll(&mut self) -> Result<Option<Message>, FrameError> {
+        loop {
+            if !self.open {
+                if self.handshake()? {
+                    continue;
+                }
+            } else if let Some(message) = self.parse_frame()? {
+                return Ok(Some(message));
+            } else if self.buffer.len() > 0 {
+                // A control frame was handled, maybe another frame is buffered
+                if self.frame_ready()? {
+                    continue;
+                }
+            }
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Ok(Some(Message::Close)),
+                Ok(size) => self.buffer.extend_from_slice(&chunk[..size]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock ||
+                              e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+                Err(e) => return Err(FrameError::Io(e)),
+            }
+        }
+    }
+
+    /**
+     * Send a text message
+     */
+    pub fn send_text(&mut self, text: &str) -> io::Result<()> {
+        self.send_frame(OPCODE_TEXT, text.as_bytes())
+    }
+
+    /**
+     * Refuse the client before the opening handshake
+     */
+    pub fn refuse(&mut self) -> io::Result<()> {
+        self.stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\
+                                Connection: close\r\n\r\n")?;
+        self.stream.flush()
+    }
+
+    /**
+     * Answer the opening handshake if the request is buffered
+     * @return true if the connection is now open
+     */
+    fn handshake(&mut self) -> Result<bool, FrameError> {
+        let end = match self.buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            Some(end) => end,
+            None => {
+                if self.buffer.len() > MAX_HANDSHAKE_SIZE {
+                    return Err(FrameError::TooLarge(self.buffer.len()));
+                }
+                return Ok(false);
+            }
+        };
+        let request = String::from_utf8_lossy(&self.buffer[..end]).into_owned();
+        self.buffer.drain(..end + 4);
+        let mut lines = request.split("\r\n");
+        let request_line = lines.next().unwrap_or("");
+        let mut upgrade = false;
+        let mut key = None;
+        for line in lines {
+            let mut parts = line.splitn(2, ':');
+            let name = parts.next().unwrap_or("").trim().to_lowercase();
+            let value = parts.next().unwrap_or("").trim();
+            if name == "upgrade" {
+                upgrade = value.to_lowercase() == "websocket";
+            } else if name == "sec-websocket-key" {
+                key = Some(String::from(value));
+            }
+        }
+        let key = match key {
+            Some(ref key) if request_line.starts_with("GET ") && upgrade => key,
+            _ => {
+                let _ = self.stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n");
+                return Err(FrameError::InvalidHeader(String::from(request_line)));
+            }
+        };
+        let response = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                                Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                               accept_key(key));
+        self.stream.write_all(response.as_bytes())?;
+        self.stream.flush()?;
+        self.open = true;
+        Ok(true)
+    }
+
+    /**
+     * @return the size of the header and of the payload of the buffered frame, if known, or an
+     * error if the payload is larger than max_message_size
+     */
+    fn frame_size(&self) -> Result<Option<(usize, usize)>, FrameError> {
+        if self.buffer.len() < 2 {
+            return Ok(None);
+        }
+        let (offset, size) = match self.buffer[1] & 0x7F {
+            126 => {
+                if self.buffer.len() < 4 {
+                    return Ok(None);
+                }
+                (4, ((self.buffer[2] as u64) << 8) | self.buffer[3] as u64)
+            }
+            127 => {
+                if self.buffer.len() < 10 {
+                    return Ok(None);
+                }
+                let mut size: u64 = 0;
+                for byte in &self.buffer[2..10] {
+                    size = (size << 8) | *byte as u64;
+                }
+                (10, size)
+            }
+            size => (2, size as u64),
+        };
+        // The size is chosen by the client, check it before using it
+        if size > self.max_message_size as u64 {
+            return Err(FrameError::TooLarge(cmp::min(size, usize::MAX as u64) as usize));
+        }
+        // Frames sent by clients are masked
+        Ok(Some((offset + 4, size as usize)))
+    }
+
+    fn frame_ready(&self) -> Result<bool, FrameError> {
+        Ok(match self.frame_size()? {
+            Some((header, size)) => {
+                header.checked_add(size).map_or(false, |end| self.buffer.len() >= end)
+            }
+            None => false,
+        })
+    }
+
+    /**
+     * Parse the buffered frame
+     * @return a complete message, or None if more data is needed
+     */
+    fn parse_frame(&mut self) -> Result<Option<Message>, FrameError> {
+        let (header, size) = match self.frame_size()? {
+            Some(frame_size) => frame_size,
+            None => return Ok(None),
+        };
+        let first = self.buffer[0];
+        if first & 0x70 != 0 {
+            return Err(FrameError::InvalidHeader(String::from("reserved bits set")));
+        }
+        if self.buffer[1] & 0x80 == 0 {
+            return Err(FrameError::InvalidHeader(String::from("unmasked frame")));
+        }
+        // Control frames are small and can't be fragmented (RFC 6455 section 5.5)
+        if first & 0x08 != 0 {
+            if first & 0x80 == 0 {
+                return Err(FrameError::InvalidHeader(String::from("fragmented control frame")));
+            }
+            if size > MAX_CONTROL_PAYLOAD {
+                return Err(FrameError::InvalidHeader(String::from("control frame too large")));
+            }
+        }
+        let buffered = self.fragments.as_ref().map(|&(_, ref payload)| payload.len()).unwrap_or(0);
+        match size.checked_add(buffered) {
+            Some(total) if total <= self.max_message_size => {}
+            _ => return Err(FrameError::TooLarge(size.saturating_add(buffered))),
+        }
+        let end = header.checked_add(size).ok_or(FrameError::TooLarge(size))?;
+        if self.buffer.len() < end {
+            return Ok(None);
+        }
+        let mask = [self.buffer[header - 4],
+                    self.buffer[header - 3],
+                    self.buffer[header - 2],
+                    self.buffer[header - 1]];
+        let payload: Vec<u8> = self.buffer[header..end]
+            .iter()
+            .enumerate()
+            .map(|(index, byte)| byte ^ mask[index % 4])
+            .collect();
+        self.buffer.drain(..end);
+
+        let fin = first & 0x80 != 0;
+        let opcode = first & 0x0F;
+        match opcode {
+            OPCODE_TEXT | OPCODE_BINARY => {
+                if self.fragments.is_some() {
+                    return Err(FrameError::InvalidHeader(String::from("unfinished message")));
+                }
+                if fin {
+                    return WebSocket::<S>::message(opcode, payload).map(Some);
+                }
+                self.fragments = Some((opcode, payload));
+                Ok(None)
+            }
+            OPCODE_CONTINUATION => {
+                let (opcode, mut message) = match self.fragments.take() {
+                    Some(fragments) => fragments,
+                    None => {
+                        return Err(FrameError::InvalidHeader(String::from("unexpected continuation")))
+                    }
+                };
+                message.extend(payload);
+                if fin {
+                    return WebSocket::<S>::message(opcode, message).map(Some);
+                }
+                self.fragments = Some((opcode, message));
+                Ok(None)
+            }
+            OPCODE_CLOSE => {
+                let _ = self.send_frame(OPCODE_CLOSE, &payload[..payload.len().min(2)]);
+                Ok(Some(Message::Close))
+            }
+            OPCODE_PING => {
+                self.send_frame(OPCODE_PONG, &payload)?;
+                Ok(None)
+            }
+            OPCODE_PONG => Ok(None),
+            _ => Err(FrameError::InvalidHeader(format!("unknown opcode {}", opcode))),
+        }
+    }
+
+    fn message(opcode: u8, payload: Vec<u8>) -> Result<Message, FrameError> {
+        if opcode == OPCODE_BINARY {
+            return Ok(Message::Binary(payload));
+        }
+        String::from_utf8(payload).map(Message::Text).map_err(|_| FrameError::InvalidUtf8)
+    }
+
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut frame = vec![0x80 | opcode];
+        let size = payload.len();
+        if size < 126 {
+            frame.push(size as u8);
+        } else if size <= 0xFFFF {
+            frame.push(126);
+            frame.push((size >> 8) as u8);
+            frame.push(size as u8);
+        } else {
+            frame.push(127);
+            for shift in (0..8).rev() {
+                frame.push(((size as u64) >> (shift * 8)) as u8);
+            }
+        }
+        frame.extend_from_slice(payload);
+        self.stream.write_all(&frame)?;
+        self.stream.flush()
+    }
+}
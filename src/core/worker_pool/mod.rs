@@ -0,0 +1,172 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/**
+ * Default number of workers
+ */
+pub const DEFAULT_WORKERS: usize = 8;
+/**
+ * Default number of jobs waiting for a worker
+ */
+pub const DEFAULT_QUEUE_SIZE: usize = 64;
+
+/**
+ * What to do when the queue is full
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OverflowPolicy {
+    /// Give the job back to the caller
+    Reject,
+    /// Wait for a free place in the queue
+    Block,
+}
+
+impl OverflowPolicy {
+    pub fn from_str(policy: &str) -> Option<OverflowPolicy> {
+        match policy {
+            "reject" => Some(OverflowPolicy::Reject),
+            "block" => Some(OverflowPolicy::Block),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Counters of a WorkerPool
+ */
+#[derive(Default, Debug)]
+pub struct PoolStats {
+    pub queue_depth: AtomicUsize,
+    pub active: AtomicUsize,
+    pub rejected: AtomicUsize,
+}
+
+#[derive(Clone, RustcEncodable, PartialEq, Debug)]
+pub struct PoolStatsSnapshot {
+    pub queue_depth: usize,
+    pub active: usize,
+    pub rejected: usize,
+}
+
+impl PoolStats {
+    pub fn snapshot(&self) -> PoolStatsSnapshot {
+        PoolStatsSnapshot {
+            queue_depth: self.queue_depth.load(Ordering::SeqCst),
+            active: self.active.load(Ordering::SeqCst),
+            rejected: self.rejected.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/**
+ * A fixed number of threads processing jobs from a bounded queue
+ */
+pub struct WorkerPool<T: Send + 'static> {
+    sender: SyncSender<T>,
+    policy: OverflowPolicy,
+    stats: Arc<PoolStats>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    /**
+     * @param size: number of workers
+     * @param queue_size: number of jobs which can wait for a worker
+     * @param policy: what to do when the queue is full
+     * @param stats: where to count jobs
+     * @param handler: called by workers for each job
+     * @return a WorkerPool
+     */
+    pub fn new<F>(size: usize,
+                  queue_size: usize,
+                  policy: OverflowPolicy,
+                  stats: Arc<PoolStats>,
+                  handler: F)
+                  -> WorkerPool<T>
+        where F: Fn(T) + Send + Sync + 'static
+    {
+        let (sender, receiver) = sync_channel(queue_size);
+        let receiver: Arc<Mutex<Receiver<T>>> = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+        let mut workers = Vec::new();
+        for _ in 0..size {
+            let receiver = receiver.clone();
+            let handler = handler.clone();
+            let stats = stats.clone();
+            workers.push(thread::spawn(move || loop {
+                let job = match receiver.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => break, // The pool is dropped
+                };
+                stats.active.fetch_add(1, Ordering::SeqCst);
+                stats.queue_depth.fetch_sub(1, Ordering::SeqCst);
+                // A panicking job must not kill the worker
+                if panic::catch_unwind(AssertUnwindSafe(|| handler(job))).is_err() {
+                    error!(target:"WorkerPool", "A job panicked");
+                }
+                stats.active.fetch_sub(1, Ordering::SeqCst);
+            }));
+        }
+        WorkerPool {
+            sender: sender,
+            policy: policy,
+            stats: stats,
+            workers: workers,
+        }
+    }
+
+    /**
+     * Queue a job
+     * @param job
+     * @return the job if it was rejected
+     */
+    pub fn submit(&self, job: T) -> Result<(), T> {
+        self.stats.queue_depth.fetch_add(1, Ordering::SeqCst);
+        let result = match self.policy {
+            OverflowPolicy::Block => self.sender.send(job).map_err(|e| e.0),
+            OverflowPolicy::Reject => {
+                self.sender.try_send(job).map_err(|e| match e {
+                    TrySendError::Full(job) => job,
+                    TrySendError::Disconnected(job) => job,
+                })
+            }
+        };
+        if result.is_err() {
+            self.stats.queue_depth.fetch_sub(1, Ordering::SeqCst);
+            self.stats.rejected.fetch_add(1, Ordering::SeqCst);
+        }
+        result
+    }
+
+    pub fn stats(&self) -> Arc<PoolStats> {
+        self.stats.clone()
+    }
+
+    /**
+     * Stop accepting jobs and wait for queued and running jobs
+     * @param timeout: how long to wait
+     * @return false if some jobs are still running after the timeout
+     */
+    pub fn shutdown(self, timeout: Duration) -> bool {
+        // None if the timeout is too large to be reached
+        let deadline = Instant::now().checked_add(timeout);
+        let stats = self.stats.clone();
+        // Workers stop when the queue is empty and the sender is dropped
+        drop(self.sender);
+        while stats.queue_depth.load(Ordering::SeqCst) > 0 ||
+              stats.active.load(Ordering::SeqCst) > 0 {
+            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        for worker in self.workers {
+            let _ = worker.join();
+        }
+        true
+    }
+}
@@ -4,32 +4,117 @@ extern crate env_logger;
 extern crate iron;
 #[macro_use]
 extern crate lazy_static;
+extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate openssl;
 extern crate petgraph;
 extern crate rustc_serialize;
 extern crate regex;
+extern crate reqwest;
 extern crate router;
 
 pub mod core;
 pub mod rori_utils;
 
+use core::auth_manager::{hash_secret, DEFAULT_ITERATIONS};
+use core::config::Config;
+use core::signals::{self, Signal};
 use core::Server;
 use core::API;
+use rustc_serialize::json;
+use std::collections::BTreeMap;
+use std::env;
+use std::io;
+use std::process;
+use std::sync::atomic::Ordering;
 use std::thread;
 
+/**
+ * Print a new entry for the authorize list of config_server.json
+ * @param name: the name of the client
+ * @param secret: the secret of the client. Read from stdin if None
+ */
+fn print_authorized_user(name: String, secret: Option<String>) {
+    let secret = secret.unwrap_or_else(|| {
+        let mut secret = String::new();
+        io::stdin().read_line(&mut secret).expect("Can't read secret");
+        String::from(secret.trim())
+    });
+    let mut user = BTreeMap::new();
+    user.insert("name", name);
+    user.insert("secret", hash_secret(&*secret, DEFAULT_ITERATIONS));
+    println!("{}", json::as_pretty_json(&user));
+}
+
 #[allow(dead_code)]
 fn main() {
     // Init logging
     env_logger::init();
 
-    // Launch API
+    // rori_server --hash-secret <name> [secret]
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 2 && args[1] == "--hash-secret" {
+        print_authorized_user(args[2].clone(), args.get(3).cloned());
+        return;
+    }
+
+    // rori_server --check-config [path]
+    let check_only = args.len() > 1 && args[1] == "--check-config";
+    let config_path = if check_only {
+        args.get(2).cloned().unwrap_or(String::from("config_server.json"))
+    } else {
+        String::from("config_server.json")
+    };
+    let config = match Config::load(&config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{} is invalid:", config_path);
+            for problem in e.problems {
+                eprintln!("  - {}", problem);
+            }
+            process::exit(1);
+        }
+    };
+    if check_only {
+        println!("{} is valid", config_path);
+        return;
+    }
+
+    // Handle SIGINT, SIGTERM and SIGHUP. Must be done before spawning threads.
+    let signals = signals::install();
     thread::spawn(move || {
-        let mut api = API::new("config_server.json");
-        api.start();
+        for signal in signals {
+            match signal {
+                Signal::Terminate => {
+                    // A second signal stops RORI without waiting
+                    if core::SHUTDOWN.load(Ordering::SeqCst) {
+                        warn!(target:"main", "Stopping now");
+                        process::exit(1);
+                    }
+                    core::shutdown();
+                }
+                Signal::Reload => core::reload(),
+            }
+        }
     });
-    // Launch RORI server
-    let mut server = Server::new("config_server.json");
+
+    // Launch API
+    let shutdown_timeout = config.shutdown_timeout;
+    let mut api = API::from_config(&config);
+    let mut server = Server::from_config(&config_path, config);
+    api.set_auth_manager(server.auth_manager());
+    api.set_workers(server.workers());
+    if let Err(e) = server.restore() {
+        error!(target:"main", "{}", e);
+        process::exit(1);
+    }
+    let mut listening = api.listen();
+    // Launch RORI server. Returns when RORI is stopped.
     server.start();
+    if !API::drain(shutdown_timeout) {
+        warn!(target:"main", "Some API requests are still processed, stop anyway");
+    }
+    let _ = listening.close();
+    info!(target:"main", "RORI stopped");
 }
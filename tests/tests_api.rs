@@ -7,6 +7,13 @@ mod tests_api {
     use main::rori_utils::data::RoriData;
     use std::thread;
     use reqwest;
+    use reqwest::StatusCode;
+
+    fn get(url: &str) -> reqwest::Response {
+        reqwest::Client::new().get(url)
+            .basic_auth("rori_desktop_client", Some("secret"))
+            .send().unwrap()
+    }
 
     #[test]
     fn test_users() {
@@ -24,33 +31,47 @@ mod tests_api {
 
         ENDPOINTMANAGER.lock().unwrap().register_endpoint(data_to_process);
 
+        // Credentials are needed
+        let response = reqwest::get("http://localhost:3000/client/AmarOk/text").unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = reqwest::Client::new().get("http://localhost:3000/client/AmarOk/text")
+            .basic_auth("rori_desktop_client", Some("wrong"))
+            .send().unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // rori_discord_bot has no api scope
+        let response = reqwest::Client::new().get("http://localhost:3000/rm/0")
+            .basic_auth("rori_discord_bot", Some("secret"))
+            .send().unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
         // Find endpoint
-        let body = reqwest::get("http://localhost:3000/client/AmarOk/text").unwrap()
+        let body = get("http://localhost:3000/client/AmarOk/text")
                    .text().unwrap();
 
         assert!(body != String::from("[]"));
 
         // Find endpoint
-        let body = reqwest::get("http://localhost:3000/client/*/text").unwrap()
+        let body = get("http://localhost:3000/client/*/text")
                    .text().unwrap();
         assert!(body != String::from("[]"));
 
         // Don't find any endpoint for user
-        let body = reqwest::get("http://localhost:3000/client/NONE/text").unwrap()
+        let body = get("http://localhost:3000/client/NONE/text")
                    .text().unwrap();
 
         assert_eq!(body, String::from("[]"));
 
         // Find endpoint
-        let body = reqwest::get("http://localhost:3000/client/*/NONE").unwrap()
+        let body = get("http://localhost:3000/client/*/NONE")
                    .text().unwrap();
 
         assert_eq!(body, String::from("[]"));
 
         // Remove endpoint
-        let _ = reqwest::get("http://localhost:3000/rm/0").unwrap()
+        let _ = get("http://localhost:3000/rm/0")
                    .text().unwrap();
-        let body = reqwest::get("http://localhost:3000/client/*/text").unwrap()
+        let body = get("http://localhost:3000/client/*/text")
                    .text().unwrap();
 
         assert_eq!(body, String::from("[]"));
@@ -0,0 +1,47 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_api_auth {
+    use main::core::api_auth::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_scopes() {
+        assert_eq!(ApiScope::from_str("words"), Some(ApiScope::Words));
+        assert_eq!(ApiScope::from_str("root"), None);
+        assert!(ApiScope::Read.granted_by(&[ApiScope::Read, ApiScope::Send]));
+        assert!(!ApiScope::Words.granted_by(&[ApiScope::Read, ApiScope::Send]));
+        assert!(ApiScope::Admin.granted_by(&[ApiScope::Admin]));
+        assert!(ApiScope::Words.granted_by(&[ApiScope::Admin]));
+        assert!(!ApiScope::Admin.granted_by(&[]));
+    }
+
+    #[test]
+    fn test_module_scopes() {
+        // Modules can't do more than the client of the data
+        assert_eq!(module_scopes(&[]), vec![]);
+        assert_eq!(module_scopes(&[ApiScope::Read]), vec![ApiScope::Read]);
+        assert_eq!(module_scopes(&[ApiScope::Words, ApiScope::Send]),
+                   vec![ApiScope::Send, ApiScope::Words]);
+        // and never get admin
+        assert_eq!(module_scopes(&[ApiScope::Admin]),
+                   vec![ApiScope::Read, ApiScope::Send, ApiScope::Words]);
+    }
+
+    #[test]
+    fn test_tokens() {
+        let tokens = TokenManager::new();
+        let token = tokens.issue("module", vec![ApiScope::Read], Duration::from_secs(60));
+        assert_eq!(token.len(), 64);
+        assert_eq!(tokens.check(&*token), Some((String::from("module"), vec![ApiScope::Read])));
+        assert_eq!(tokens.check("unknown"), None);
+        tokens.revoke(&*token);
+        assert_eq!(tokens.check(&*token), None);
+
+        // Expired
+        let token = tokens.issue("module", vec![ApiScope::Read], Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(tokens.check(&*token), None);
+    }
+}
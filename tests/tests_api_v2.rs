@@ -0,0 +1,208 @@
+extern crate main;
+extern crate reqwest;
+
+#[cfg(test)]
+mod tests_api_v2 {
+    use main::core::*;
+    use main::core::api_error::ApiError;
+    use main::core::auth_manager::hash_secret;
+    use main::core::endpoint_manager::Pusher;
+    use main::rori_utils::data::RoriData;
+    use reqwest;
+    use reqwest::{Method, StatusCode};
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::prelude::*;
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use std::time::Duration;
+
+    fn request(method: Method, path: &str, client: &str, body: &str) -> (StatusCode, String) {
+        let mut response = reqwest::Client::new()
+            .request(method, &*format!("http://localhost:14141{}", path))
+            .basic_auth(client, Some("secret"))
+            .body(String::from(body))
+            .send()
+            .unwrap();
+        (response.status(), response.text().unwrap())
+    }
+
+    fn request_error(method: Method, path: &str, client: &str, body: &str) -> ApiError {
+        let (status, body) = request(method, path, client, body);
+        let error = ApiError::from_json(&*body).unwrap();
+        assert_eq!(error.status, status.as_u16());
+        error
+    }
+
+    #[test]
+    /**
+     * test status codes and errors of the v2 API
+     */
+    fn test_v2() {
+        let dir = env::temp_dir().join("rori_tests_api_v2");
+        fs::create_dir_all(&dir).unwrap();
+        let config_file = dir.join("config_server.json");
+        File::create(&config_file)
+            .unwrap()
+            .write_all(format!("{{\"ip\":\"127.0.0.1\",\"port\":\"0\",\"api_ip\":\"127.0.0.1\",\
+                                \"api_port\":\"14141\",\"cert\":\"cert.pem\",\"key\":\"key.pem\",\
+                                \"authorize\":[{{\"name\":\"admin\",\"secret\":\"{}\",\
+                                \"api_scopes\":[\"admin\"]}},\
+                                {{\"name\":\"reader\",\"secret\":\"{}\",\
+                                \"api_scopes\":[\"read\"]}}]}}",
+                               hash_secret("secret", 1000),
+                               hash_secret("secret", 1000))
+                           .as_bytes())
+            .unwrap();
+        thread::spawn(move || {
+            let mut api = API::new(config_file);
+            api.start();
+        });
+        thread::sleep(Duration::from_millis(500));
+
+        let (sender, receiver) = channel();
+        let data = RoriData::new(String::from("AmarOk"),
+                                 String::from("websocket|light"),
+                                 String::from("admin"),
+                                 String::from("register"),
+                                 String::new());
+        let id = ENDPOINTMANAGER.lock()
+            .unwrap()
+            .register_pushed_endpoint(data,
+                                      Pusher {
+                                          connection: 0,
+                                          sender: sender,
+                                      })
+            .unwrap();
+
+        // Credentials and scopes
+        let error = request_error(Method::GET, "/v2/endpoints?datatype=light", "nobody", "");
+        assert_eq!((error.status, &*error.code), (401, "unauthorized"));
+        let error = request_error(Method::DELETE, &*format!("/v2/endpoints/{}", id), "reader", "");
+        assert_eq!((error.status, &*error.code), (403, "forbidden"));
+
+        // Endpoints
+        let error = request_error(Method::GET, "/v2/endpoints", "reader", "");
+        assert_eq!((error.status, &*error.message), (400, "datatype is missing"));
+        let (status, body) = request(Method::GET, "/v2/endpoints?datatype=light&owner=AmarOk",
+                                     "reader", "");
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("\"owner\":\"AmarOk\""));
+        let (_, body) = request(Method::GET, "/v2/endpoints?datatype=light&owner=nobody",
+                                "reader", "");
+        assert_eq!(body, "[]");
+        let (_, body) = request(Method::GET, "/v2/endpoints?datatype=light&status=online",
+                                "reader", "");
+        assert!(body.contains("\"owner\":\"AmarOk\""));
+        let (_, body) = request(Method::GET, "/v2/endpoints?datatype=light&status=offline",
+                                "reader", "");
+        assert_eq!(body, "[]");
+        let error = request_error(Method::GET, "/v2/endpoints?datatype=light&status=up", "reader", "");
+        assert_eq!(error.status, 400);
+
+        let (status, _) = request(Method::POST, &*format!("/v2/endpoints/{}/data", id),
+                                  "admin", "on");
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(1)).unwrap(), "on");
+        let error = request_error(Method::POST, "/v2/endpoints/9999/data", "admin", "on");
+        assert_eq!((error.status, &*error.code), (404, "not_found"));
+        let error = request_error(Method::POST, "/send/9999", "admin", "on");
+        assert_eq!((error.status, &*error.code), (404, "not_found"));
+        let error = request_error(Method::DELETE, "/v2/endpoints/abc", "admin", "");
+        assert_eq!((error.status, &*error.code), (400, "bad_request"));
+        let (status, _) = request(Method::DELETE, &*format!("/v2/endpoints/{}", id), "admin", "");
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let error = request_error(Method::DELETE, &*format!("/v2/endpoints/{}", id), "admin", "");
+        assert_eq!(error.status, 404);
+
+        // Dead letters
+        let error = request_error(Method::GET, "/v2/dead-letters", "reader", "");
+        assert_eq!(error.status, 403);
+        let (status, body) = request(Method::GET, "/v2/dead-letters", "admin", "");
+        assert_eq!((status, &*body), (StatusCode::OK, "[]"));
+        let error = request_error(Method::POST, "/v2/dead-letters/9999/replay", "admin", "");
+        assert_eq!((error.status, &*error.message), (404, "unknown dead letter"));
+        let error = request_error(Method::DELETE, "/v2/dead-letters/abc", "admin", "");
+        assert_eq!((error.status, &*error.message), (400, "invalid dead letter id"));
+
+        // Reprocess
+        let error = request_error(Method::POST, "/v2/reprocess", "admin", "{\"author\":");
+        assert_eq!(error.status, 400);
+        let (status, body) = request(Method::POST, "/v2/reprocess", "admin",
+                                     "{\"author\":\"AmarOk\",\"content\":\"hello\",\
+                                      \"client\":\"admin\",\"datatype\":\"unknown\",\
+                                      \"secret\":\"\"}");
+        assert_eq!((status, &*body), (StatusCode::OK, "{\"matched\":false,\"replies\":[]}"));
+
+        // Monitoring
+        let (status, body) = request(Method::GET, "/healthz", "nobody", "");
+        assert_eq!((status, &*body), (StatusCode::OK, "{\"status\":\"ok\"}"));
+        // The Server isn't started
+        let (status, body) = request(Method::GET, "/readyz", "nobody", "");
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body.contains("\"listeners\":false"));
+        let error = request_error(Method::GET, "/metrics", "nobody", "");
+        assert_eq!(error.status, 401);
+        let (status, body) = request(Method::GET, "/metrics", "reader", "");
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("rori_endpoint_deliveries_total{transport=\"websocket\"} 1\n"));
+        assert!(body.contains("rori_registered_endpoints 0\n"));
+
+        // Events, without secrets
+        let mut events = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap()
+            .get("http://localhost:14141/events?client=admin&datatype=text")
+            .basic_auth("reader", Some("secret"))
+            .send()
+            .unwrap();
+        assert_eq!(events.status(), StatusCode::OK);
+        let mut connected = [0; 13];
+        events.read_exact(&mut connected).unwrap();
+        assert_eq!(&connected, b": connected\n\n");
+        let (status, _) = request(Method::POST, "/v2/ingest", "nobody",
+                                  "{\"author\":\"AmarOk\",\"content\":\"hello\",\
+                                   \"client\":\"admin\",\"datatype\":\"text\",\
+                                   \"secret\":\"secret\"}");
+        assert_eq!(status, StatusCode::OK);
+        let mut received = String::new();
+        let mut buffer = [0; 1024];
+        while !received.contains("\"event\":\"authorization\"") {
+            let size = events.read(&mut buffer).unwrap();
+            assert!(size > 0);
+            received.push_str(&*String::from_utf8_lossy(&buffer[..size]));
+        }
+        assert!(received.starts_with("data: {"));
+        assert!(received.contains("\"event\":\"message_received\""));
+        assert!(received.contains("\"result\":\"accepted\""));
+        assert!(received.contains("\"secret\":\"[redacted]\""));
+        assert!(!received.contains("\"secret\":\"secret\""));
+        drop(events);
+
+        // The OpenAPI document is public
+        let mut response = reqwest::get("http://localhost:14141/openapi.json").unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.text().unwrap().contains("\"operationId\":\"v2_reprocess\""));
+
+        // Every v2 route answers JSON
+        let response = reqwest::Client::new()
+            .get("http://localhost:14141/v2/stats")
+            .basic_auth("reader", Some("secret"))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("Content-Type").unwrap(), "application/json");
+
+        // Old routes still work, but are deprecated
+        let response = reqwest::Client::new()
+            .get("http://localhost:14141/stats")
+            .basic_auth("reader", Some("secret"))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("Deprecation").unwrap(), "true");
+        assert_eq!(response.headers().get("Link").unwrap(),
+                   "</v2/stats>; rel=\"successor-version\"");
+    }
+}
@@ -0,0 +1,158 @@
+extern crate main;
+extern crate openssl;
+
+#[cfg(test)]
+mod tests_auth_manager {
+    use main::core::auth_manager::*;
+    use main::rori_utils::data::RoriData;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509, X509Builder, X509NameBuilder};
+
+    fn generate_certificate(common_name: &str) -> X509 {
+        let pkey = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, common_name).unwrap();
+        let name = name.build();
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&pkey).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        builder.sign(&pkey, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    fn data_with(client: &str, secret: &str, datatype: &str, author: &str) -> RoriData {
+        RoriData::from_json(format!("{{\"author\":\"{}\",\"content\":\"hello\",\
+                                     \"client\":\"{}\",\"datatype\":\"{}\",\
+                                     \"secret\":\"{}\"}}",
+                                    author,
+                                    client,
+                                    datatype,
+                                    secret))
+    }
+
+    fn data_from(client: &str, secret: &str) -> RoriData {
+        data_with(client, secret, "text", "AmarOk")
+    }
+
+    #[test]
+    /**
+     * test if clients can authenticate with a certificate
+     */
+    fn test_client_certificate() {
+        let irc_cert = generate_certificate("irc_entry_module");
+        let discord_cert = generate_certificate("rori_discord_bot");
+        let unknown_cert = generate_certificate("unknown");
+        let discord_identity = PeerIdentity::from_certificate(&discord_cert);
+        assert_eq!(discord_identity.fingerprint.len(), 64);
+
+        let auth_manager = AuthManager::new(vec![AuthorizedUser {
+                                                     name: Some(String::from("irc_entry_module")),
+                                                     cert_subject: Some(String::from("irc_entry_module")),
+                                                     ..Default::default()
+                                                 },
+                                                 AuthorizedUser {
+                                                     name: Some(String::from("rori_discord_bot")),
+                                                     cert_fingerprint: Some(discord_identity.fingerprint.to_uppercase()),
+                                                     ..Default::default()
+                                                 }]);
+
+        let irc_identity = PeerIdentity::from_certificate(&irc_cert);
+        assert!(auth_manager.is_authorized(&data_from("irc_entry_module", ""), Some(&irc_identity)));
+        assert!(auth_manager.is_authorized(&data_from("rori_discord_bot", ""),
+                                           Some(&discord_identity)));
+        // A certificate can't be used to speak for another client
+        assert!(!auth_manager.is_authorized(&data_from("rori_discord_bot", ""), Some(&irc_identity)));
+        // Unknown certificates and missing certificates need a secret
+        let unknown_identity = PeerIdentity::from_certificate(&unknown_cert);
+        assert!(!auth_manager.is_authorized(&data_from("irc_entry_module", ""),
+                                            Some(&unknown_identity)));
+        assert!(!auth_manager.is_authorized(&data_from("irc_entry_module", ""), None));
+    }
+
+    #[test]
+    /**
+     * test if clients can still authenticate with a secret
+     */
+    fn test_secret() {
+        let auth_manager = AuthManager::new(vec![AuthorizedUser {
+            name: Some(String::from("irc_entry_module")),
+            secret: Some(String::from("2BB80D537B1DA3E38BD30361AA855686BDE0EACD7162FEF6A25FE97BF527A25B")),
+            ..Default::default()
+        }]);
+        assert!(auth_manager.is_authorized(&data_from("IRC_entry_module", "secret"), None));
+        assert!(!auth_manager.is_authorized(&data_from("irc_entry_module", "wrong"), None));
+        assert!(!auth_manager.is_authorized(&data_from("rori_discord_bot", "secret"), None));
+    }
+
+    #[test]
+    /**
+     * test if salted secrets can be generated and verified
+     */
+    fn test_salted_secret() {
+        let first = hash_secret("secret", 1000);
+        let second = hash_secret("secret", 1000);
+        assert!(first.starts_with("$pbkdf2-sha256$1000$"));
+        assert!(first != second);
+        assert!(verify_secret(&*first, "secret"));
+        assert!(verify_secret(&*second, "secret"));
+        assert!(!verify_secret(&*first, "wrong"));
+        assert!(!verify_secret("$pbkdf2-sha256$0$00$00", "secret"));
+        assert!(!verify_secret("$unknown$1000$00$00", "secret"));
+    }
+
+    #[test]
+    /**
+     * test if old secrets are accepted during a rotation window
+     */
+    fn test_secret_rotation() {
+        let mut user = AuthorizedUser {
+            name: Some(String::from("irc_entry_module")),
+            secret: Some(hash_secret("new", 1000)),
+            secrets: Some(vec![hash_secret("old", 1000)]),
+            ..Default::default()
+        };
+        let auth_manager = AuthManager::new(vec![user.clone()]);
+        assert!(auth_manager.is_authorized(&data_from("irc_entry_module", "new"), None));
+        assert!(auth_manager.is_authorized(&data_from("irc_entry_module", "old"), None));
+
+        // The window is closed
+        user.secrets_expire = Some(String::from("1"));
+        let auth_manager = AuthManager::new(vec![user]);
+        assert!(auth_manager.is_authorized(&data_from("irc_entry_module", "new"), None));
+        assert!(!auth_manager.is_authorized(&data_from("irc_entry_module", "old"), None));
+    }
+
+    #[test]
+    /**
+     * test if clients can only send what their scopes allow
+     */
+    fn test_scopes() {
+        let irc = AuthorizedUser {
+            name: Some(String::from("irc_entry_module")),
+            datatypes: Some(vec![String::from("text")]),
+            can_register: Some(false),
+            owners: Some(vec![String::from("AmarOk")]),
+            ..Default::default()
+        };
+        assert!(irc.can_send(&data_with("irc_entry_module", "", "text", "AmarOk")));
+        assert!(!irc.can_send(&data_with("irc_entry_module", "", "music", "AmarOk")));
+        assert!(!irc.can_send(&data_with("irc_entry_module", "", "register", "AmarOk")));
+        assert!(!irc.can_send(&data_with("irc_entry_module", "", "text", "someone")));
+
+        let desktop = AuthorizedUser {
+            name: Some(String::from("rori_desktop_client")),
+            owners: Some(vec![String::from("*")]),
+            ..Default::default()
+        };
+        assert!(desktop.can_send(&data_with("rori_desktop_client", "", "register", "someone")));
+        assert!(desktop.can_send(&data_with("rori_desktop_client", "", "music", "AmarOk")));
+    }
+}
@@ -0,0 +1,161 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_config {
+    use main::core::config::*;
+    use main::core::webhooks::WEBHOOK_EVENTS;
+    use main::core::worker_pool::OverflowPolicy;
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::prelude::*;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn write_config(name: &str, content: &str) -> PathBuf {
+        let dir = env::temp_dir().join("rori_tests_config");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        File::create(&path).unwrap().write_all(content.as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    /**
+     * test that values are typed
+     */
+    fn test_valid_config() {
+        let config = Config::read("config_server.json").unwrap();
+        assert_eq!(config.listeners,
+                   vec![Listener::Tls("0.0.0.0:1412".parse().unwrap())]);
+        assert_eq!(config.cert, Some(PathBuf::from("key/cert.pem")));
+        assert_eq!(config.overflow, OverflowPolicy::Reject);
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(DEFAULT_SHUTDOWN_TIMEOUT));
+        assert_eq!(config.authorize.len(), 3);
+    }
+
+    #[test]
+    /**
+     * test that every problem is reported
+     */
+    fn test_invalid_config() {
+        let path = write_config("invalid.json",
+                                "{\"ip\":\"localhost\",\"port\":\"99999\",\"api_ip\":\"127.0.0.1\",\
+                                 \"cert\":\"missing_cert.pem\",\"workers\":\"0\",\
+                                 \"overflow\":\"drop\",\"authorize\":[{\"name\":\"entry\"},\
+                                 {\"name\":\"Entry\"}]}");
+        let problems = Config::load(&path).unwrap_err().problems;
+        assert_eq!(problems,
+                   vec![String::from("ip: \"localhost\" is not an IP address"),
+                        String::from("port: \"99999\" is not a port"),
+                        String::from("api_port is missing"),
+                        String::from("cert: missing_cert.pem doesn't exist"),
+                        String::from("key is missing"),
+                        String::from("workers must be greater than 0"),
+                        String::from("overflow: \"drop\" must be reject or block"),
+                        String::from("authorize: Entry is defined twice")]);
+        // Files are only checked by load
+        assert_eq!(Config::read(&path).unwrap_err().problems.len(), 7);
+
+        let path = write_config("invalid_rate_limits.json",
+                                "{\"api_ip\":\"127.0.0.1\",\"api_port\":\"3000\",\
+                                 \"listeners\":[{\"transport\":\"tcp\",\"address\":\"127.0.0.1:1413\"}],\
+                                 \"authorize\":[{\"name\":\"entry\",\
+                                 \"rate_limit\":{\"rate\":0,\"burst\":5},\
+                                 \"author_rate_limit\":{\"rate\":1,\"burst\":0}}]}");
+        assert_eq!(Config::load(&path).unwrap_err().problems,
+                   vec![String::from("authorize: client #0 rate_limit.rate must be greater than 0"),
+                        String::from("authorize: client #0 author_rate_limit.burst must be at least 1")]);
+
+        // Durations are bounded, so deadlines can't overflow
+        let path = write_config("invalid_durations.json",
+                                "{\"api_ip\":\"127.0.0.1\",\"api_port\":\"3000\",\
+                                 \"listeners\":[{\"transport\":\"tcp\",\"address\":\"127.0.0.1:1413\"}],\
+                                 \"shutdown_timeout\":\"18446744073709551615\",\
+                                 \"endpoint_lease\":\"18446744073709551615\"}");
+        assert_eq!(Config::load(&path).unwrap_err().problems,
+                   vec![String::from("shutdown_timeout must be at most 3600"),
+                        String::from("endpoint_lease must be at most 604800")]);
+
+        assert_eq!(Config::load("missing_config.json").unwrap_err().problems.len(), 1);
+        let path = write_config("not_json.json", "{\"ip\":");
+        assert_eq!(Config::load(&path).unwrap_err().problems.len(), 1);
+    }
+
+    #[test]
+    /**
+     * test the webhooks list
+     */
+    fn test_webhooks() {
+        let path = write_config("webhooks.json",
+                                "{\"api_ip\":\"127.0.0.1\",\"api_port\":\"3000\",\
+                                 \"listeners\":[{\"transport\":\"tcp\",\"address\":\"127.0.0.1:1413\"}],\
+                                 \"webhook_retries\":\"2\",\"webhooks\":[\
+                                 {\"url\":\"https://example.com/rori\",\"secret\":\"s\",\
+                                 \"events\":[\"delivery_failed\"]},\
+                                 {\"url\":\"http://127.0.0.1:8080\",\"secret\":\"s\"}]}");
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.webhook_retries, 2);
+        assert_eq!(config.webhooks[0].events, vec![String::from("delivery_failed")]);
+        // Every event by default
+        assert_eq!(config.webhooks[1].events.len(), WEBHOOK_EVENTS.len());
+
+        let path = write_config("invalid_webhooks.json",
+                                "{\"api_ip\":\"127.0.0.1\",\"api_port\":\"3000\",\
+                                 \"listeners\":[{\"transport\":\"tcp\",\"address\":\"127.0.0.1:1413\"}],\
+                                 \"webhook_retries\":\"65\",\
+                                 \"webhooks\":[{\"url\":\"ftp://example.com\",\"secret\":\"s\"},\
+                                 {\"url\":\"http://127.0.0.1:8080\"},\
+                                 {\"url\":\"http://127.0.0.1:8080\",\"secret\":\"s\",\
+                                 \"events\":[\"message_received\"]}]}");
+        assert_eq!(Config::load(&path).unwrap_err().problems,
+                   vec![String::from("webhooks #0: \"ftp://example.com\" is not an http or https URL"),
+                        String::from("webhooks #1: secret is missing"),
+                        String::from("webhooks #2: unknown event \"message_received\""),
+                        String::from("webhook_retries must be at most 50")]);
+    }
+
+    #[test]
+    /**
+     * test the listeners list
+     */
+    fn test_listeners() {
+        let path = write_config("listeners.json",
+                                "{\"api_ip\":\"127.0.0.1\",\"api_port\":\"3000\",\"listeners\":[\
+                                 {\"transport\":\"unix\",\"path\":\"/tmp/rori.sock\",\"mode\":\"660\"},\
+                                 {\"transport\":\"tcp\",\"address\":\"[::1]:1413\"}]}");
+        // No TLS listener, so no certificate
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.listeners,
+                   vec![Listener::Unix(PathBuf::from("/tmp/rori.sock"), Some(0o660)),
+                        Listener::Plain("[::1]:1413".parse().unwrap())]);
+
+        let path = write_config("invalid_listeners.json",
+                                "{\"api_ip\":\"127.0.0.1\",\"api_port\":\"3000\",\"listeners\":[\
+                                 {\"address\":\"[::]:1412\"},\
+                                 {\"transport\":\"tcp\",\"address\":\"0.0.0.0:1413\"},\
+                                 {\"transport\":\"unix\",\"mode\":\"999\"},\
+                                 {\"transport\":\"udp\"}]}");
+        assert_eq!(Config::load(&path).unwrap_err().problems,
+                   vec![String::from("listeners[1].address: plaintext listeners must use a \
+                                      loopback address"),
+                        String::from("listeners[2].path is missing"),
+                        String::from("listeners[2].mode: \"999\" is not an octal mode"),
+                        String::from("listeners[3].address is missing"),
+                        String::from("listeners[3].transport: \"udp\" must be tls, tcp, unix, wss or \
+                                      ws"),
+                        String::from("cert is missing"),
+                        String::from("key is missing")]);
+    }
+
+    #[test]
+    /**
+     * test that RORI_* variables replace values of the file
+     */
+    fn test_env_override() {
+        let mut vars = HashMap::new();
+        vars.insert(String::from("RORI_API_PORT"), String::from("3412"));
+        let config = Config::read_with_env("config_server.json", &vars).unwrap();
+        assert_eq!(config.api_address, "0.0.0.0:3412".parse().unwrap());
+    }
+}
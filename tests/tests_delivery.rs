@@ -0,0 +1,89 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_delivery {
+    use main::core::{DELIVERIES, ENDPOINTMANAGER};
+    use main::core::delivery::*;
+    use main::core::endpoint_manager::Pusher;
+    use main::rori_utils::data::RoriData;
+    use std::env;
+    use std::fs;
+    use std::sync::mpsc::{channel, Sender};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn register(sender: Sender<String>) -> u64 {
+        let data = RoriData::new(String::from("AmarOk"),
+                                 String::from("websocket|light"),
+                                 String::from("rori_desktop_client"),
+                                 String::from("register"),
+                                 String::new());
+        let pusher = Pusher {
+            connection: 7,
+            sender: sender,
+        };
+        ENDPOINTMANAGER.lock().unwrap().register_pushed_endpoint(data, pusher).unwrap()
+    }
+
+    /**
+     * Wait until there are some dead letters
+     */
+    fn wait_dead_letters(count: usize) -> Vec<DeadLetter> {
+        let start = Instant::now();
+        while DELIVERIES.dead_letters().len() < count && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(20));
+        }
+        DELIVERIES.dead_letters()
+    }
+
+    #[test]
+    /**
+     * test retries, dead letters and replays
+     */
+    fn test_delivery() {
+        let dir = env::temp_dir().join("rori_tests_delivery");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dead_letters.json");
+        let _ = fs::remove_file(&path);
+        DELIVERIES.set_retries(2, Duration::from_millis(50));
+        assert_eq!(DELIVERIES.open(&path).unwrap(), 0);
+
+        // Data is sent in order
+        let (sender, receiver) = channel();
+        let id = register(sender);
+        for data in &["1", "2", "3"] {
+            assert!(DELIVERIES.send(id, String::from(*data)));
+        }
+        for data in &["1", "2", "3"] {
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(1)).unwrap(), *data);
+        }
+
+        // The connection is closed: retried, then kept
+        drop(receiver);
+        let start = Instant::now();
+        DELIVERIES.send(id, String::from("on"));
+        let dead_letters = wait_dead_letters(1);
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!((dead_letters[0].endpoint, &*dead_letters[0].data, dead_letters[0].attempts),
+                   (id, "on", 3));
+        assert_eq!(dead_letters[0].error, "connection closed");
+        DELIVERIES.send(9999, String::from("off"));
+        let dead_letters = wait_dead_letters(2);
+        assert_eq!(dead_letters[1].error, "unknown endpoint");
+        assert_eq!(DELIVERIES.pending(), 0);
+
+        // Dead letters are saved
+        assert_eq!(DeliveryQueue::new().open(&path).unwrap(), 2);
+
+        // The endpoint is back
+        let (sender, receiver) = channel();
+        assert_eq!(register(sender), id);
+        assert!(DELIVERIES.replay(dead_letters[0].id));
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(1)).unwrap(), "on");
+        assert!(!DELIVERIES.replay(dead_letters[0].id));
+        assert!(DELIVERIES.remove_dead_letter(dead_letters[1].id).is_some());
+        assert_eq!(DELIVERIES.dead_letters().len(), 0);
+        assert_eq!(DeliveryQueue::new().open(&path).unwrap(), 0);
+    }
+}
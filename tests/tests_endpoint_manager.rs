@@ -0,0 +1,211 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_endpoint_manager {
+    use main::core::endpoint_manager::*;
+    use main::rori_utils::data::RoriData;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::prelude::*;
+    use std::path::PathBuf;
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use std::time::Duration;
+
+    fn store(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join("rori_tests_endpoint_manager");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn register(content: &str) -> RoriData {
+        RoriData::new(String::from("AmarOk"),
+                      String::from(content),
+                      String::from("rori_desktop_client"),
+                      String::from("register"),
+                      String::new())
+    }
+
+    #[test]
+    /**
+     * test that endpoints and their ids survive a restart
+     */
+    fn test_persistence() {
+        let path = store("endpoints.json");
+        let mut manager = EndpointManager::new();
+        assert_eq!(manager.open(&path).unwrap(), 0);
+        assert_eq!(manager.register_endpoint(register("127.0.0.1:1415|text")), Some(0));
+        assert_eq!(manager.register_endpoint(register("127.0.0.1:1416|music")), Some(1));
+        let (sender, _receiver) = channel();
+        let pusher = Pusher {
+            connection: 0,
+            sender: sender,
+        };
+        assert_eq!(manager.register_pushed_endpoint(register("websocket|light"), pusher), Some(2));
+        assert!(!fs::read_to_string(&path).unwrap().contains("websocket:"));
+        assert!(manager.remove_endpoint(1));
+
+        // Restart: WebSocket endpoints are gone, ids are not reused
+        let mut manager = EndpointManager::new();
+        assert_eq!(manager.open(&path).unwrap(), 1);
+        assert_eq!(manager.get_endpoint(0).unwrap().address, "127.0.0.1:1415");
+        assert!(manager.get_endpoint(1).is_none());
+        assert!(manager.get_endpoint(2).is_none());
+        assert_eq!(manager.register_endpoint(register("127.0.0.1:1417|text")), Some(3));
+        // Duplicates keep their id
+        assert_eq!(manager.register_endpoint(register("127.0.0.1:1415|text")), Some(0));
+        assert!(!path.with_extension("json.tmp").exists());
+
+        // Client names are not case sensitive, like for authentication
+        let mut duplicate = register("127.0.0.1:1415|text");
+        duplicate.client = String::from("RORI_Desktop_Client");
+        assert_eq!(manager.register_endpoint(duplicate), Some(0));
+
+        // Endpoints remove themselves
+        let mut unregister = register("127.0.0.1:1415");
+        unregister.datatype = String::from("unregister");
+        unregister.client = String::from("RORI_Desktop_Client");
+        assert_eq!(manager.unregister(&unregister), 1);
+        assert_eq!(manager.unregister(&unregister), 0);
+        assert_eq!(EndpointManager::new().open(&path).unwrap(), 1);
+    }
+
+    #[test]
+    /**
+     * test files written by previous versions and invalid files
+     */
+    fn test_open() {
+        let path = store("legacy_endpoints.json");
+        File::create(&path)
+            .unwrap()
+            .write_all(b"[{\"name\":\"rori_desktop_client\",\"id\":4,\"owner\":\"AmarOk\",\
+                         \"address\":\"127.0.0.1:1415\",\"compatible_data\":[\"text\"]}]")
+            .unwrap();
+        let mut manager = EndpointManager::new();
+        assert_eq!(manager.open(&path).unwrap(), 1);
+        assert_eq!(manager.register_endpoint(register("127.0.0.1:1416|text")), Some(5));
+        let id = manager.register_endpoint(register("{\"address\":\"127.0.0.1:1417\",\
+                                                      \"datatypes\":[\"text\"],\
+                                                      \"display_name\":\"Desktop\",\
+                                                      \"capabilities\":{\"volume\":[0,100]}}"))
+            .unwrap();
+        let endpoint = manager.get_endpoint(id).unwrap();
+        let mut manager = EndpointManager::new();
+        assert_eq!(manager.open(&path).unwrap(), 3);
+        assert_eq!(manager.get_endpoint(id), Some(endpoint));
+
+        // Restored ids can't be given to other endpoints
+        let mut manager = EndpointManager::new();
+        for port in 1420..1425 {
+            manager.register_endpoint(register(&*format!("127.0.0.1:{}|text", port))).unwrap();
+        }
+        assert!(manager.open(&path).is_err());
+        assert_eq!(manager.get_endpoint(5), None);
+
+        let path = store("invalid_endpoints.json");
+        File::create(&path).unwrap().write_all(b"{\"next_id\":").unwrap();
+        assert!(EndpointManager::new().open(&path).is_err());
+    }
+
+    #[test]
+    /**
+     * test that endpoints without heartbeat go offline, then are removed
+     */
+    fn test_lease() {
+        let mut manager = EndpointManager::new();
+        manager.set_lease(Duration::from_millis(200));
+        let id = manager.register_endpoint(register("127.0.0.1:1415|text")).unwrap();
+        let other = manager.register_endpoint(register("127.0.0.1:1416|text")).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(manager.heartbeat(&RoriData::new(String::from("AmarOk"),
+                                                    String::from("127.0.0.1:1416"),
+                                                    String::from("rori_desktop_client"),
+                                                    String::from("heartbeat"),
+                                                    String::new())),
+                   1);
+        manager.expire();
+        assert!(!manager.is_online(id));
+        assert!(manager.is_online(other));
+        let offline = manager.get_endpoint_for_type(String::from("text"),
+                                                    String::from("AmarOk"),
+                                                    Liveness::Offline);
+        assert_eq!(offline.len(), 1);
+        assert_eq!(offline[0].id, id);
+        assert_eq!(manager.get_endpoint_for_type(String::from("text"),
+                                                 String::from("AmarOk"),
+                                                 Liveness::Any)
+                       .len(),
+                   2);
+
+        // Registering again renews the lease
+        assert_eq!(manager.register_endpoint(register("127.0.0.1:1415|text")), Some(id));
+        assert!(manager.is_online(id));
+        thread::sleep(Duration::from_millis(450));
+        manager.expire();
+        assert_eq!(manager.count(), 0);
+
+        // A lease too long to be doubled doesn't overflow
+        manager.set_lease(Duration::from_secs(u64::MAX));
+        manager.register_endpoint(register("127.0.0.1:1415|text")).unwrap();
+        manager.expire();
+        assert_eq!(manager.count(), 1);
+    }
+
+    #[test]
+    /**
+     * test JSON and legacy registrations
+     */
+    fn test_registration() {
+        let registration = Registration::parse("127.0.0.1:1415|text|music").unwrap();
+        assert_eq!(registration.address, "127.0.0.1:1415");
+        assert_eq!(registration.datatypes,
+                   vec![String::from("text"), String::from("music")]);
+        assert_eq!(registration.protocol_version, 0);
+
+        let registration = Registration::parse("{\"address\":\"[::1]:1415\",\
+                                                \"datatypes\":[\"text\"],\
+                                                \"display_name\":\"Desktop\",\
+                                                \"tags\":[\"home\"],\
+                                                \"tls\":{\"server_name\":\"rori.local\"},\
+                                                \"capabilities\":{\"tts\":true}}")
+            .unwrap();
+        assert!(registration.validate().is_ok());
+        assert_eq!(registration.display_name, Some(String::from("Desktop")));
+        assert_eq!(registration.tags, vec![String::from("home")]);
+        assert_eq!(registration.protocol_version, 1);
+        assert_eq!(registration.tls.server_name, Some(String::from("rori.local")));
+        assert_eq!(registration.capabilities.find("tts").and_then(|tts| tts.as_boolean()),
+                   Some(true));
+
+        let invalid = ["127.0.0.1|text",
+                       "127.0.0.1:1415",
+                       "{\"address\":\"localhost:99999\",\"datatypes\":[\"text\"]}",
+                       "{\"address\":\"localhost:1415\",\"datatypes\":\"text\"}",
+                       "{\"address\":\"localhost:1415\",\"datatypes\":[\"text\"],\
+                        \"tls\":{\"fingerprint\":\"ab:cd\"}}",
+                       "{\"address\":\"localhost:1415\",\"datatypes\":[\"text\"],\
+                        \"protocol_version\":99}",
+                       "{\"address\":\"localhost:1415\",\"datatypes\":[\"text\"],\
+                        \"capabilities\":[]}",
+                       "{\"address\":"];
+        let mut manager = EndpointManager::new();
+        for content in invalid.iter() {
+            assert_eq!(manager.register_endpoint(register(content)), None, "{}", content);
+        }
+        assert_eq!(manager.count(), 0);
+
+        // Registering again updates the metadata
+        let id = manager.register_endpoint(register("{\"address\":\"127.0.0.1:1415\",\
+                                                      \"datatypes\":[\"text\",\"music\"]}"))
+            .unwrap();
+        assert_eq!(manager.register_endpoint(register("{\"address\":\"127.0.0.1:1415\",\
+                                                        \"datatypes\":[\"music\",\"text\"],\
+                                                        \"tags\":[\"office\"]}")),
+                   Some(id));
+        assert_eq!(manager.register_endpoint(register("127.0.0.1:1415|text|music")), Some(id));
+        assert_eq!(manager.count(), 1);
+        assert_eq!(manager.get_endpoint(id).unwrap().protocol_version, 0);
+    }
+}
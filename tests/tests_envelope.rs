@@ -0,0 +1,83 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_envelope {
+    use main::core::auth_manager::*;
+    use main::core::envelope::*;
+    use main::rori_utils::data::RoriData;
+
+    const PAYLOAD: &'static str = "{\"author\":\"AmarOk\",\"content\":\"hello\",\
+                                   \"client\":\"irc_entry_module\",\"datatype\":\"text\",\
+                                   \"secret\":\"\"}";
+
+    fn auth_manager() -> AuthManager {
+        AuthManager::new(vec![AuthorizedUser {
+            name: Some(String::from("irc_entry_module")),
+            signing_key: Some(String::from("key")),
+            require_signature: Some(true),
+            ..Default::default()
+        }])
+    }
+
+    #[test]
+    /**
+     * test if a signed envelope is accepted only once
+     */
+    fn test_replay() {
+        let auth_manager = auth_manager();
+        let data = RoriData::from_json(String::from(PAYLOAD));
+        let mut envelope = Envelope::new("irc_entry_module", "nonce1", PAYLOAD);
+        envelope.sign("key");
+        let envelope = Envelope::from_json(&*envelope.to_json()).unwrap();
+        assert!(envelope.verify("key"));
+
+        assert!(auth_manager.authenticate_envelope(&envelope, &data).is_some());
+        assert!(auth_manager.authenticate_envelope(&envelope, &data).is_none());
+        // The client must sign its data
+        assert!(auth_manager.authenticate(&data, None).is_none());
+    }
+
+    #[test]
+    /**
+     * test if invalid envelopes are rejected
+     */
+    fn test_invalid_envelopes() {
+        let auth_manager = auth_manager();
+        let data = RoriData::from_json(String::from(PAYLOAD));
+
+        let mut envelope = Envelope::new("irc_entry_module", "nonce1", PAYLOAD);
+        envelope.sign("wrong key");
+        assert!(auth_manager.authenticate_envelope(&envelope, &data).is_none());
+
+        let mut envelope = Envelope::new("irc_entry_module", "nonce2", PAYLOAD);
+        envelope.timestamp -= DEFAULT_MAX_CLOCK_SKEW + 10;
+        envelope.sign("key");
+        assert!(auth_manager.authenticate_envelope(&envelope, &data).is_none());
+
+        let mut envelope = Envelope::new("irc_entry_module", "nonce3", PAYLOAD);
+        envelope.sign("key");
+        envelope.payload = envelope.payload.replace("hello", "bye");
+        assert!(auth_manager.authenticate_envelope(&envelope, &data).is_none());
+    }
+
+    #[test]
+    /**
+     * test if the nonce cache stays bounded
+     */
+    fn test_nonce_cache() {
+        let mut cache = NonceCache::new(2);
+        assert!(cache.insert("a", 100, 0));
+        assert!(!cache.insert("a", 100, 0));
+        assert!(cache.insert("b", 90, 0));
+        // Full of nonces in the clock skew window: new nonces are rejected, not the old ones
+        assert!(!cache.insert("c", 100, 0));
+        assert!(!cache.insert("a", 100, 0));
+        assert_eq!(cache.len(), 2);
+        // Old nonces are forgotten, even if not received first
+        assert!(cache.insert("d", 100, 95));
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.insert("a", 100, 95));
+        assert!(cache.insert("e", 200, 150));
+        assert_eq!(cache.len(), 1);
+    }
+}
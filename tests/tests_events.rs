@@ -0,0 +1,113 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_events {
+    use main::core::events::*;
+    use main::core::metrics::Gauges;
+    use main::core::METRICS;
+    use main::rori_utils::data::RoriData;
+    use std::time::Duration;
+
+    fn data(client: &str, author: &str, datatype: &str) -> RoriData {
+        RoriData::new(String::from(author),
+                      String::from("hello"),
+                      String::from(client),
+                      String::from(datatype),
+                      String::from("secret"))
+    }
+
+    #[test]
+    /**
+     * test that events are filtered by client, author and datatype
+     */
+    fn test_filter() {
+        let event = Event::about("message_received", &data("irc_entry_module", "AmarOk", "text"));
+        assert!(EventFilter::default().matches(&event));
+        let mut filter = EventFilter::default();
+        filter.author = Some(String::from("amarok"));
+        assert!(filter.matches(&event));
+        filter.datatype = Some(String::from("music"));
+        assert!(!filter.matches(&event));
+        filter.datatype = Some(String::from("text"));
+        filter.client = Some(String::from("rori_discord_bot"));
+        assert!(!filter.matches(&event));
+        // Events without datatype don't match a datatype filter
+        let filter = EventFilter {
+            datatype: Some(String::from("text")),
+            ..EventFilter::default()
+        };
+        assert!(!filter.matches(&Event::new("endpoint_removed")));
+    }
+
+    #[test]
+    /**
+     * test that secrets are never written
+     */
+    fn test_redact() {
+        let data = data("irc_entry_module", "AmarOk", "text");
+        let json = Event::about("message_received", &data).with("data", redact(&data)).to_json();
+        assert!(!json.contains("\"secret\":\"secret\""));
+        assert!(json.contains("\"secret\":\"[redacted]\""));
+        assert!(json.contains("\"event\":\"message_received\""));
+        assert!(json.contains("\"client\":\"irc_entry_module\""));
+    }
+
+    #[test]
+    fn test_bus() {
+        let bus = EventBus::new();
+        // Events are only built for subscribers
+        bus.publish(|| panic!("nobody listens"));
+
+        let (id, receiver) = bus.subscribe(EventFilter {
+                client: Some(String::from("irc_entry_module")),
+                ..EventFilter::default()
+            })
+            .unwrap();
+        bus.publish(|| Event::about("message_received", &data("rori_discord_bot", "AmarOk", "text")));
+        bus.publish(|| Event::about("message_received", &data("irc_entry_module", "AmarOk", "text")));
+        let event = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(event.contains("\"client\":\"irc_entry_module\""));
+        assert!(receiver.try_recv().is_err());
+
+        bus.unsubscribe(id);
+        bus.publish(|| panic!("nobody listens"));
+
+        let subscribers: Vec<_> = (0..MAX_SUBSCRIBERS)
+            .map(|_| bus.subscribe(EventFilter::default()).unwrap())
+            .collect();
+        assert!(bus.subscribe(EventFilter::default()).is_none());
+        drop(subscribers);
+        // Subscribers which are gone are forgotten
+        bus.publish(|| Event::new("endpoint_removed"));
+        assert!(bus.subscribe(EventFilter::default()).is_some());
+    }
+
+    #[test]
+    /**
+     * test that events dropped for a slow subscriber are counted
+     */
+    fn test_slow_subscriber() {
+        let bus = EventBus::new();
+        let (_, receiver) = bus.subscribe(EventFilter::default()).unwrap();
+        for _ in 0..300 {
+            bus.publish(|| Event::new("endpoint_removed"));
+        }
+        let output = METRICS.render(&Gauges {
+            registered_endpoints: 0,
+            queue_depth: 0,
+            pending_deliveries: 0,
+            dead_letters: 0,
+        });
+        let dropped: f64 = output.lines()
+            .find(|line| line.starts_with("rori_events_dropped_total "))
+            .and_then(|line| line.split(' ').nth(1))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(dropped >= 44.);
+        // The subscriber gets new events once it reads
+        assert_eq!(receiver.try_iter().count(), 256);
+        bus.publish(|| Event::new("endpoint_removed"));
+        assert!(receiver.try_recv().is_ok());
+    }
+}
@@ -0,0 +1,68 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_framing {
+    use main::core::framing::*;
+    use std::io::Cursor;
+
+    #[test]
+    /**
+     * test if several frames can be read from the same stream
+     */
+    fn test_pipelined_frames() {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, None, "{\"content\":\"hello\"}").unwrap();
+        write_frame(&mut stream, Some("42"), "{\"content\":\"été\"}").unwrap();
+        let mut reader = FrameReader::new(Cursor::new(stream), DEFAULT_MAX_FRAME_SIZE);
+
+        let first = reader.read_frame().unwrap().unwrap();
+        assert_eq!(first.body, "{\"content\":\"hello\"}");
+        assert_eq!(first.id, None);
+        let second = reader.read_frame().unwrap().unwrap();
+        assert_eq!(second.body, "{\"content\":\"été\"}");
+        assert_eq!(second.id, Some(String::from("42")));
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    #[test]
+    /**
+     * test if old clients sending one message padded with NUL bytes still work
+     */
+    fn test_legacy_message() {
+        let mut stream = String::from("{\"content\":\"ŝ\"}").into_bytes();
+        stream.extend_from_slice(&[0u8; 32]);
+        let mut reader = FrameReader::new(Cursor::new(stream), DEFAULT_MAX_FRAME_SIZE);
+
+        let frame = reader.read_frame().unwrap().unwrap();
+        assert_eq!(frame.version, 0);
+        assert_eq!(frame.body, "{\"content\":\"ŝ\"}");
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    #[test]
+    /**
+     * test if invalid frames are rejected
+     */
+    fn test_invalid_frames() {
+        let stream = String::from("RORI/1 64\n{}").into_bytes();
+        let mut reader = FrameReader::new(Cursor::new(stream), 16);
+        match reader.read_frame() {
+            Err(FrameError::TooLarge(64)) => {}
+            _ => panic!("frame should be too large"),
+        }
+
+        let stream = String::from("RORI/2 2\n{}").into_bytes();
+        let mut reader = FrameReader::new(Cursor::new(stream), 16);
+        match reader.read_frame() {
+            Err(FrameError::UnsupportedVersion(2)) => {}
+            _ => panic!("version should be unsupported"),
+        }
+
+        let stream = String::from("RORI/1 two\n{}").into_bytes();
+        let mut reader = FrameReader::new(Cursor::new(stream), 16);
+        match reader.read_frame() {
+            Err(FrameError::InvalidHeader(_)) => {}
+            _ => panic!("header should be invalid"),
+        }
+    }
+}
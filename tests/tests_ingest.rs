@@ -0,0 +1,114 @@
+extern crate main;
+extern crate reqwest;
+
+#[cfg(test)]
+mod tests_ingest {
+    use main::core::*;
+    use main::core::auth_manager::hash_secret;
+    use main::core::config::Config;
+    use main::core::endpoint_manager::Liveness;
+    use main::core::reply::{Reply, ReplyStatus};
+    use reqwest;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::prelude::*;
+    use std::thread;
+    use std::time::Duration;
+
+    fn data(client: &str, secret: &str, datatype: &str, content: &str) -> String {
+        format!("{{\"author\":\"AmarOk\",\"content\":\"{}\",\"client\":\"{}\",\
+                 \"datatype\":\"{}\",\"secret\":\"{}\"}}",
+                content,
+                client,
+                datatype,
+                secret)
+    }
+
+    fn ingest(body: String) -> (u16, Reply) {
+        ingest_on(14140, body)
+    }
+
+    fn ingest_on(port: u16, body: String) -> (u16, Reply) {
+        let mut response = reqwest::Client::new()
+            .post(&*format!("http://localhost:{}/ingest", port))
+            .body(body)
+            .send()
+            .unwrap();
+        let reply = Reply::from_json(&*response.text().unwrap()).unwrap();
+        (response.status().as_u16(), reply)
+    }
+
+    #[test]
+    /**
+     * test that /ingest authorizes and processes data like the server
+     */
+    fn test_ingest() {
+        let dir = env::temp_dir().join("rori_tests_ingest");
+        fs::create_dir_all(&dir).unwrap();
+        let config_file = dir.join("config_server.json");
+        File::create(&config_file)
+            .unwrap()
+            .write_all(format!("{{\"ip\":\"127.0.0.1\",\"port\":\"0\",\"api_ip\":\"127.0.0.1\",\
+                                \"api_port\":\"14140\",\"max_frame_size\":\"1024\",\
+                                \"cert\":\"cert.pem\",\"key\":\"key.pem\",\
+                                \"authorize\":[{{\"name\":\"ci\",\"secret\":\"{}\",\
+                                \"datatypes\":[\"text\"],\"can_register\":false}},\
+                                {{\"name\":\"hub\",\"secret\":\"{}\"}}]}}",
+                               hash_secret("secret", 1000),
+                               hash_secret("secret", 1000))
+                           .as_bytes())
+            .unwrap();
+        let path = config_file.clone();
+        thread::spawn(move || {
+            let mut api = API::new(path);
+            api.start();
+        });
+        thread::sleep(Duration::from_millis(500));
+
+        let (status, reply) = ingest(data("ci", "bad", "text", "hello"));
+        assert_eq!((status, reply.status), (401, ReplyStatus::Unauthorized));
+        let (status, reply) = ingest(data("ci", "secret", "text", "hello"));
+        assert_eq!((status, reply.status), (200, ReplyStatus::NoModuleMatched));
+        let (status, reply) = ingest(data("ci", "secret", "register", "127.0.0.1:4000|text"));
+        assert_eq!((status, reply.status), (403, ReplyStatus::Forbidden));
+        let (status, reply) = ingest(data("hub", "secret", "register", "127.0.0.1:4000|light"));
+        assert_eq!((status, reply.status), (200, ReplyStatus::Accepted));
+        let endpoint_id = reply.endpoint_id.unwrap();
+        assert_eq!(ENDPOINTMANAGER.lock()
+                       .unwrap()
+                       .get_endpoint_for_type(String::from("light"),
+                                              String::from("AmarOk"),
+                                              Liveness::Online)
+                       .len(),
+                   1);
+        let (status, reply) = ingest(data("hub", "secret", "register", "127.0.0.1|light"));
+        assert_eq!((status, reply.status), (400, ReplyStatus::Malformed));
+        let (status, reply) = ingest(data("hub", "secret", "heartbeat", "127.0.0.1:4000"));
+        assert_eq!((status, reply.status), (200, ReplyStatus::Accepted));
+        let (status, reply) = ingest(data("hub", "secret", "heartbeat", "127.0.0.1:4001"));
+        assert_eq!((status, reply.status), (404, ReplyStatus::UnknownEndpoint));
+        let (status, reply) = ingest(data("ci", "secret", "heartbeat", ""));
+        assert_eq!((status, reply.status), (403, ReplyStatus::Forbidden));
+        let (_, reply) = ingest(data("hub", "secret", "register", "127.0.0.1:4000|light"));
+        assert_eq!(reply.endpoint_id, Some(endpoint_id));
+        let (status, reply) = ingest(data("hub", "secret", "unregister", "127.0.0.1:4000"));
+        assert_eq!((status, reply.status), (200, ReplyStatus::Accepted));
+        assert!(ENDPOINTMANAGER.lock().unwrap().get_endpoint(endpoint_id).is_none());
+        let (status, reply) = ingest(data("hub", "secret", "unregister", ""));
+        assert_eq!((status, reply.status), (404, ReplyStatus::UnknownEndpoint));
+        let (status, reply) = ingest(String::from("{\"author\":"));
+        assert_eq!((status, reply.status), (400, ReplyStatus::Malformed));
+        let (status, reply) = ingest(data("hub", "secret", "text", &*"a".repeat(1024)));
+        assert_eq!((status, reply.status), (400, ReplyStatus::Malformed));
+
+        // With a Server, data is processed by its workers: busy while it doesn't run
+        let mut config = Config::read(&config_file).unwrap();
+        config.api_address = "127.0.0.1:14142".parse().unwrap();
+        let mut api = API::from_config(&config);
+        api.set_workers(Server::from_config(&config_file, config).workers());
+        let mut listening = api.listen();
+        let (status, reply) = ingest_on(14142, data("ci", "secret", "text", "hello"));
+        assert_eq!((status, reply.status), (503, ReplyStatus::Busy));
+        let _ = listening.close();
+    }
+}
@@ -0,0 +1,54 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_metrics {
+    use main::core::metrics::*;
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    #[test]
+    /**
+     * test the Prometheus text format
+     */
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.message_received("irc_entry_module", "text");
+        metrics.message_received("irc_entry_module", "text");
+        metrics.message_received("rori_discord_bot", "music");
+        metrics.auth_failed("unauthorized");
+        metrics.module_executed("talk", Duration::from_millis(1500));
+        metrics.module_executed("talk", Duration::from_millis(500));
+        metrics.delivered("tls", true);
+        metrics.delivered("tls", false);
+        metrics.message_received("quote\"d\\", "text");
+        let output = metrics.render(&Gauges {
+            registered_endpoints: 3,
+            queue_depth: 1,
+            pending_deliveries: 4,
+            dead_letters: 2,
+        });
+
+        assert!(output.contains("# TYPE rori_messages_received_total counter\n"));
+        assert!(output.contains("rori_messages_received_total{client=\"irc_entry_module\",\
+                                 datatype=\"text\"} 2\n"));
+        assert!(output.contains("rori_messages_received_total{client=\"rori_discord_bot\",\
+                                 datatype=\"music\"} 1\n"));
+        assert!(output.contains("rori_messages_received_total{client=\"quote\\\"d\\\\\",\
+                                 datatype=\"text\"} 1\n"));
+        assert!(output.contains("rori_auth_failures_total{reason=\"unauthorized\"} 1\n"));
+        assert!(output.contains("rori_module_executions_total{module=\"talk\"} 2\n"));
+        assert!(output.contains("rori_module_duration_seconds_total{module=\"talk\"} 2\n"));
+        assert!(output.contains("rori_endpoint_deliveries_total{transport=\"tls\"} 1\n"));
+        assert!(output.contains("rori_endpoint_delivery_failures_total{transport=\"tls\"} 1\n"));
+        assert!(output.contains("# TYPE rori_registered_endpoints gauge\nrori_registered_endpoints 3\n"));
+        assert!(output.contains("rori_worker_queue_depth 1\n"));
+        assert!(output.contains("rori_pending_deliveries 4\n"));
+        assert!(output.contains("rori_dead_letters 2\n"));
+        // Each metric has its own description
+        let helps: Vec<&str> = output.lines()
+            .filter(|line| line.starts_with("# HELP "))
+            .map(|line| line.splitn(4, ' ').nth(3).unwrap())
+            .collect();
+        assert_eq!(helps.iter().collect::<HashSet<_>>().len(), helps.len());
+    }
+}
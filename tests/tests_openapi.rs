@@ -0,0 +1,78 @@
+extern crate main;
+extern crate rustc_serialize;
+
+#[cfg(test)]
+mod tests_openapi {
+    use main::core::openapi::*;
+    use rustc_serialize::json::Json;
+
+    /**
+     * @return every "$ref" of a JSON document
+     */
+    fn refs(json: &Json, found: &mut Vec<String>) {
+        match *json {
+            Json::Object(ref object) => {
+                for (key, value) in object {
+                    if key == "$ref" {
+                        found.push(String::from(value.as_string().unwrap()));
+                    }
+                    refs(value, found);
+                }
+            }
+            Json::Array(ref array) => {
+                for value in array {
+                    refs(value, found);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    /**
+     * test that every route is described
+     */
+    fn test_document() {
+        let document = document();
+        assert_eq!(document.find("openapi").unwrap().as_string(), Some("3.0.0"));
+        let paths = document.find("paths").unwrap().as_object().unwrap();
+        let mut ids = Vec::new();
+        for route in ROUTES {
+            assert!(!ids.contains(&route.id), "{} is duplicated", route.id);
+            ids.push(route.id);
+            let operation = paths.get(&route.openapi_path())
+                .and_then(|path| path.find(route.method))
+                .unwrap_or_else(|| panic!("{} is not in the document", route.id));
+            assert_eq!(operation.find("operationId").unwrap().as_string(), Some(route.id));
+            let parameters = operation.find("parameters").unwrap().as_array().unwrap();
+            let path_parameters = route.path.split('/').filter(|s| s.starts_with(':')).count();
+            assert_eq!(parameters.len(), path_parameters + route.query.len());
+            assert_eq!(operation.find("deprecated").is_some(), route.successor.is_some());
+            assert_eq!(operation.find("security").is_some(), route.scope.is_some());
+            if let Some(successor) = route.successor {
+                assert!(ROUTES.iter().any(|route| route.path == successor && route.successor.is_none()));
+            }
+        }
+        assert_eq!(find("v2_stats").unwrap().openapi_path(), "/v2/stats");
+        assert_eq!(find("v2_send").unwrap().openapi_path(), "/v2/endpoints/{id}/data");
+        assert!(find("unknown").is_none());
+
+        // Every schema is defined
+        let schemas = document.find_path(&["components", "schemas"]).unwrap();
+        let mut found = Vec::new();
+        refs(&document, &mut found);
+        assert!(found.len() > 0);
+        for reference in found {
+            let name = reference.split('/').last().unwrap();
+            assert!(schemas.find(name).is_some(), "{} is not defined", reference);
+        }
+    }
+
+    #[test]
+    fn test_help() {
+        let help = help();
+        for route in ROUTES {
+            assert!(help.contains(&*format!("{} {} ", route.method.to_uppercase(), route.path)));
+        }
+    }
+}
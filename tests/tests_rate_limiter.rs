@@ -0,0 +1,73 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_rate_limiter {
+    use main::core::rate_limiter::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    /**
+     * test if a bucket allows a burst and then refills
+     */
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new();
+        let limit = RateLimit {
+            rate: 20.,
+            burst: 2.,
+        };
+        assert!(limiter.check("client:irc", &limit));
+        assert!(limiter.check("client:irc", &limit));
+        assert!(!limiter.check("client:irc", &limit));
+        assert_eq!(limiter.limited(), 1);
+        // Other buckets are not affected
+        assert!(limiter.check("client:discord", &limit));
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(limiter.check("client:irc", &limit));
+    }
+
+    #[test]
+    /**
+     * test that full buckets are forgotten, whatever the limit of the new bucket
+     */
+    fn test_forget_full_buckets() {
+        let limiter = RateLimiter::new();
+        let fast = RateLimit {
+            rate: 100.,
+            burst: 1.,
+        };
+        let slow = RateLimit {
+            rate: 0.001,
+            burst: 10.,
+        };
+        for client in 0..10000 {
+            assert!(limiter.check(&*format!("client:{}", client), &fast));
+        }
+        assert_eq!(limiter.buckets(), 10000);
+        thread::sleep(Duration::from_millis(100));
+        assert!(limiter.check("client:slow", &slow));
+        assert_eq!(limiter.buckets(), 1);
+    }
+
+    #[test]
+    /**
+     * test that the least recently used buckets are forgotten when none is full
+     */
+    fn test_max_buckets() {
+        let limiter = RateLimiter::new();
+        let slow = RateLimit {
+            rate: 0.001,
+            burst: 1.,
+        };
+        assert!(limiter.check("author:irc:first", &slow));
+        for author in 0..20000 {
+            assert!(limiter.check(&*format!("author:irc:{}", author), &slow));
+            assert!(limiter.buckets() <= 10000);
+        }
+        // Recent buckets are kept
+        assert!(!limiter.check("author:irc:19999", &slow));
+        // Old ones are forgotten
+        assert!(limiter.check("author:irc:first", &slow));
+    }
+}
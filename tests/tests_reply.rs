@@ -0,0 +1,36 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_reply {
+    use main::core::reply::*;
+
+    #[test]
+    /**
+     * test if replies are correctly encoded for clients
+     */
+    fn test_reply_json() {
+        let mut reply = Reply::new(Some(String::from("42")), ReplyStatus::NoModuleMatched);
+        reply.replies.push(String::from("pong"));
+        let json = reply.to_json();
+        assert_eq!(json,
+                   "{\"id\":\"42\",\"status\":\"no_module_matched\",\"replies\":[\"pong\"],\
+                    \"endpoint_id\":null}");
+        assert_eq!(Reply::from_json(&*json), Some(reply.clone()));
+        // Replies of previous versions have no endpoint_id
+        assert_eq!(Reply::from_json("{\"id\":\"42\",\"status\":\"no_module_matched\",\
+                                     \"replies\":[\"pong\"]}"),
+                   Some(reply));
+        assert_eq!(Reply::from_json("{\"id\":\"1\",\"status\":\"unknown\",\"replies\":[]}"),
+                   None);
+    }
+
+    #[test]
+    /**
+     * test if an id is generated when the client doesn't give one
+     */
+    fn test_generated_id() {
+        let first = Reply::new(None, ReplyStatus::Accepted);
+        let second = Reply::new(None, ReplyStatus::Accepted);
+        assert!(first.id != second.id);
+    }
+}
@@ -0,0 +1,270 @@
+extern crate main;
+extern crate openssl;
+
+#[cfg(test)]
+mod tests_server {
+    use main::core;
+    use main::core::auth_manager::hash_secret;
+    use main::core::endpoint_manager::Liveness;
+    use main::core::Server;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+    use openssl::x509::{X509Builder, X509NameBuilder};
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::BufReader;
+    use std::io::prelude::*;
+    use std::net::TcpStream;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+
+    fn write_file(path: &Path, content: &[u8]) {
+        File::create(path).unwrap().write_all(content).unwrap();
+    }
+
+    /**
+     * Write a self signed certificate and its key in dir
+     */
+    fn generate_certificate(dir: &Path, name: &str) {
+        let pkey = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_nid(Nid::COMMONNAME, "rori").unwrap();
+        let subject = subject.build();
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&subject).unwrap();
+        builder.set_issuer_name(&subject).unwrap();
+        builder.set_pubkey(&pkey).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        builder.sign(&pkey, MessageDigest::sha256()).unwrap();
+        write_file(&dir.join(format!("{}_cert.pem", name)),
+                   &builder.build().to_pem().unwrap());
+        write_file(&dir.join(format!("{}_key.pem", name)),
+                   &pkey.private_key_to_pem_pkcs8().unwrap());
+    }
+
+    fn config(dir: &Path, cert: &str, key: &str) -> String {
+        format!("{{\"ip\":\"127.0.0.1\",\"port\":\"0\",\"api_ip\":\"127.0.0.1\",\"api_port\":\"0\",\
+                 \"cert\":\"{}\",\"key\":\"{}\",\
+                 \"authorize\":[{{\"name\":\"irc_entry_module\",\"secret\":\"\"}}]}}",
+                dir.join(cert).display(),
+                dir.join(key).display())
+    }
+
+    #[test]
+    /**
+     * test that only valid configurations are reloaded
+     */
+    fn test_reload() {
+        let dir = env::temp_dir().join("rori_tests_server_reload");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        generate_certificate(&dir, "first");
+        generate_certificate(&dir, "second");
+        let config_file = dir.join("config_server.json");
+        write_file(&config_file,
+                   config(&dir, "first_cert.pem", "first_key.pem").as_bytes());
+        let mut server = Server::new(&config_file);
+        assert!(server.reload().is_ok());
+
+        // Invalid json
+        write_file(&config_file, b"{\"ip\":");
+        assert!(server.reload().is_err());
+        // Missing certificate
+        write_file(&config_file,
+                   config(&dir, "missing_cert.pem", "first_key.pem").as_bytes());
+        assert!(server.reload().is_err());
+        // The key doesn't match the certificate
+        write_file(&config_file,
+                   config(&dir, "first_cert.pem", "second_key.pem").as_bytes());
+        assert!(server.reload().is_err());
+        // Rotated certificate
+        write_file(&config_file,
+                   config(&dir, "second_cert.pem", "second_key.pem").as_bytes());
+        assert!(server.reload().is_ok());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    /**
+     * test that a Unix listener never replaces a file or the socket of a running server
+     */
+    fn test_unix_socket_path() {
+        let dir = env::temp_dir().join("rori_tests_server_unix_path");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config_file = dir.join("config_server.json");
+        let listen_on = |path: &Path| {
+            write_file(&config_file,
+                       format!("{{\"api_ip\":\"127.0.0.1\",\"api_port\":\"0\",\"listeners\":[\
+                                {{\"transport\":\"unix\",\"path\":\"{}\"}}]}}",
+                               path.display())
+                           .as_bytes());
+            // start returns at once when it can't listen
+            Server::new(&config_file).start();
+        };
+        // A mistyped path
+        listen_on(&config_file);
+        assert!(String::from_utf8(fs::read(&config_file).unwrap()).unwrap().contains("unix"));
+        // Another server listens on the socket
+        let socket = dir.join("rori.sock");
+        let _listener = UnixListener::bind(&socket).unwrap();
+        listen_on(&socket);
+        assert!(UnixStream::connect(&socket).is_ok());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /**
+     * Read a text message sent by the server
+     */
+    fn read_message<S: Read>(stream: &mut S) -> String {
+        let mut header = [0; 2];
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!(header[0], 0x81);
+        let mut size = header[1] as usize;
+        if size == 126 {
+            let mut extended = [0; 2];
+            stream.read_exact(&mut extended).unwrap();
+            size = ((extended[0] as usize) << 8) | extended[1] as usize;
+        }
+        let mut message = vec![0; size];
+        stream.read_exact(&mut message).unwrap();
+        String::from_utf8(message).unwrap()
+    }
+
+    /**
+     * Send a masked text message
+     */
+    fn send_message<S: Write>(stream: &mut S, message: &str) {
+        let size = message.len();
+        let mut frame = vec![0x81, 0x80 | 126, (size >> 8) as u8, size as u8, 1, 2, 3, 4];
+        frame.extend(message.bytes().enumerate().map(|(index, byte)| byte ^ (index % 4 + 1) as u8));
+        stream.write_all(&frame).unwrap();
+    }
+
+    /**
+     * Send a frame and read the reply
+     */
+    fn send<S: Read + Write>(stream: &mut S, body: &str) -> String {
+        write!(stream, "RORI/1 {} 1\n{}", body.len(), body).unwrap();
+        read_reply(stream)
+    }
+
+    /**
+     * Read a reply sent by the server
+     */
+    fn read_reply<S: Read>(stream: &mut S) -> String {
+        let mut reader = BufReader::new(stream);
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        let length: usize = header.trim().split(' ').nth(1).unwrap().parse().unwrap();
+        let mut reply = vec![0; length];
+        reader.read_exact(&mut reply).unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    #[test]
+    /**
+     * test that clients can connect without TLS on a Unix socket, on loopback and with a
+     * WebSocket, and that a client which doesn't do the TLS handshake doesn't block them
+     */
+    fn test_local_listeners() {
+        let dir = env::temp_dir().join("rori_tests_server_listeners");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        generate_certificate(&dir, "server");
+        let socket = dir.join("rori.sock");
+        let config_file = dir.join("config_server.json");
+        write_file(&config_file,
+                   format!("{{\"api_ip\":\"127.0.0.1\",\"api_port\":\"0\",\"listeners\":[\
+                            {{\"transport\":\"unix\",\"path\":\"{}\",\"mode\":\"600\"}},\
+                            {{\"transport\":\"tcp\",\"address\":\"127.0.0.1:14131\"}},\
+                            {{\"transport\":\"ws\",\"address\":\"127.0.0.1:14132\"}},\
+                            {{\"transport\":\"tls\",\"address\":\"127.0.0.1:14133\"}}],\
+                            \"cert\":\"{}\",\"key\":\"{}\",\"workers\":\"1\",\
+                            \"max_connections\":\"6\",\
+                            \"authorize\":[{{\"name\":\"irc_entry_module\",\"secret\":\"{}\"}}]}}",
+                           socket.display(),
+                           dir.join("server_cert.pem").display(),
+                           dir.join("server_key.pem").display(),
+                           hash_secret("secret", 1000))
+                       .as_bytes());
+        let mut server = Server::new(&config_file);
+        let server = thread::spawn(move || server.start());
+        thread::sleep(Duration::from_millis(500));
+
+        let data = "{\"author\":\"AmarOk\",\"content\":\"hello\",\"client\":\"irc_entry_module\",\
+                    \"datatype\":\"unknown\",\"secret\":\"secret\"}";
+        // The socket is created with its mode
+        assert_eq!(fs::metadata(&socket).unwrap().permissions().mode() & 0o777, 0o600);
+        // A client which doesn't do the TLS handshake doesn't block the others
+        let _silent_stream = TcpStream::connect("127.0.0.1:14133").unwrap();
+        thread::sleep(Duration::from_millis(200));
+        // An idle client doesn't take the only worker
+        let _idle_stream = UnixStream::connect(&socket).unwrap();
+        let mut unix_stream = UnixStream::connect(&socket).unwrap();
+        assert!(send(&mut unix_stream, data).contains("no_module_matched"));
+        let mut tcp_stream = TcpStream::connect("127.0.0.1:14131").unwrap();
+        assert!(send(&mut tcp_stream, &*data.replace("\"secret\"}", "\"bad\"}"))
+            .contains("unauthorized"));
+        let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+        connector.set_verify(SslVerifyMode::NONE);
+        let mut tls_stream = connector.build()
+            .connect("rori", TcpStream::connect("127.0.0.1:14133").unwrap())
+            .unwrap();
+        assert!(send(&mut tls_stream, data).contains("no_module_matched"));
+
+        // Register an endpoint with a WebSocket, and receive data on it
+        let mut websocket = TcpStream::connect("127.0.0.1:14132").unwrap();
+        write!(websocket,
+               "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+                Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n")
+            .unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            let mut byte = [0; 1];
+            websocket.read_exact(&mut byte).unwrap();
+            response.push(byte[0]);
+        }
+        assert!(response.starts_with(b"HTTP/1.1 101"));
+        // Too many clients: the next one is refused as soon as it connects
+        let mut refused_stream = UnixStream::connect(&socket).unwrap();
+        assert!(read_reply(&mut refused_stream).contains("\"status\":\"busy\""));
+        let register = "{\"author\":\"AmarOk\",\"content\":\"browser|text\",\
+                        \"client\":\"irc_entry_module\",\"datatype\":\"register\",\
+                        \"secret\":\"secret\"}";
+        send_message(&mut websocket, register);
+        assert!(read_message(&mut websocket).contains("\"status\":\"accepted\""));
+        let endpoints = core::ENDPOINTMANAGER.lock()
+            .unwrap()
+            .get_endpoint_for_type(String::from("text"), String::from("AmarOk"), Liveness::Online);
+        assert_eq!(endpoints.len(), 1);
+        assert!(endpoints[0].address.starts_with("websocket:"));
+        core::ENDPOINTMANAGER.lock().unwrap().send_to_endpoint(endpoints[0].id, &String::from("pushed"));
+        assert_eq!(read_message(&mut websocket), "pushed");
+        send_message(&mut websocket, "not json");
+        assert!(read_message(&mut websocket).contains("\"status\":\"malformed\""));
+        // The endpoint is removed with its connection
+        drop(websocket);
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(core::ENDPOINTMANAGER.lock()
+                       .unwrap()
+                       .get_endpoint_for_type(String::from("text"), String::from("AmarOk"), Liveness::Any)
+                       .len(),
+                   0);
+
+        core::shutdown();
+        server.join().unwrap();
+        // The socket is removed when RORI stops
+        assert!(!socket.exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,115 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_webhooks {
+    use main::core::webhooks::*;
+    use std::io::prelude::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::channel;
+    use std::time::{Duration, Instant};
+
+    /**
+     * Read a HTTP request and answer with a status
+     * @return the headers (lowercase) and the body of the request
+     */
+    fn answer(mut stream: TcpStream, status: &str) -> (String, String) {
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let size = stream.read(&mut buffer).unwrap();
+            request.extend_from_slice(&buffer[..size]);
+            let text = String::from_utf8_lossy(&request).into_owned();
+            if let Some(end) = text.find("\r\n\r\n") {
+                let headers = text[..end].to_lowercase();
+                let length = headers.lines()
+                    .find(|line| line.starts_with("content-length:"))
+                    .and_then(|line| line[15..].trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    let body = String::from(&text[end + 4..end + 4 + length]);
+                    let _ = stream.write_all(format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\
+                                                      Connection: close\r\n\r\n",
+                                                     status)
+                        .as_bytes());
+                    return (headers, body);
+                }
+            }
+            assert!(size > 0);
+        }
+    }
+
+    #[test]
+    fn test_sign() {
+        // HMAC-SHA256 test vector (RFC 4231, test case 2)
+        assert_eq!(sign("Jefe", "what do ya want for nothing?"),
+                   "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    /**
+     * test that events are signed and retried
+     */
+    fn test_dispatch() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let dispatcher = WebhookDispatcher::new(vec![Webhook {
+                                                         url: url,
+                                                         secret: String::from("secret"),
+                                                         events: vec![String::from("delivery_failed")],
+                                                     }],
+                                                1);
+        let (sender, receiver) = channel();
+        let handle = dispatcher.spawn(receiver);
+        // Not sent to this webhook
+        sender.send(String::from("{\"event\":\"endpoint_removed\",\"id\":1}")).unwrap();
+        let event = "{\"event\":\"delivery_failed\",\"id\":2}";
+        sender.send(String::from(event)).unwrap();
+
+        // The first call fails, the retry succeeds
+        let (headers, body) = answer(listener.accept().unwrap().0, "500 Internal Server Error");
+        let failed = Instant::now();
+        assert_eq!(body, event);
+        assert!(headers.starts_with("post /hook "));
+        assert!(headers.contains("x-rori-event: delivery_failed"));
+        assert!(headers.contains(&*format!("x-rori-signature: sha256={}", sign("secret", event))));
+        let (_, body) = answer(listener.accept().unwrap().0, "200 OK");
+        assert_eq!(body, event);
+        assert!(failed.elapsed() >= Duration::from_millis(900));
+
+        // Give up after the retries
+        sender.send(String::from(event)).unwrap();
+        answer(listener.accept().unwrap().0, "503 Service Unavailable");
+        answer(listener.accept().unwrap().0, "503 Service Unavailable");
+        drop(sender);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    /**
+     * test that a webhook which doesn't answer doesn't delay the others
+     */
+    fn test_independent_webhooks() {
+        let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let webhook = |listener: &TcpListener| {
+            Webhook {
+                url: format!("http://{}/hook", listener.local_addr().unwrap()),
+                secret: String::from("secret"),
+                events: vec![String::from("delivery_failed")],
+            }
+        };
+        let dispatcher = WebhookDispatcher::new(vec![webhook(&silent), webhook(&listener)], 0);
+        let (sender, receiver) = channel();
+        let handle = dispatcher.spawn(receiver);
+        let event = "{\"event\":\"delivery_failed\",\"id\":1}";
+        sender.send(String::from(event)).unwrap();
+        let _silent_stream = silent.accept().unwrap();
+        let sent = Instant::now();
+        let (_, body) = answer(listener.accept().unwrap().0, "200 OK");
+        assert_eq!(body, event);
+        assert!(sent.elapsed() < Duration::from_secs(2));
+        drop(sender);
+        handle.join().unwrap();
+    }
+}
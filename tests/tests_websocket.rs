@@ -0,0 +1,164 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_websocket {
+    use main::core::framing::FrameError;
+    use main::core::websocket::*;
+    use std::io;
+    use std::io::prelude::*;
+    use std::io::Cursor;
+
+    /**
+     * A stream reading prepared bytes and keeping what is written
+     */
+    struct Duplex {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /**
+     * Build a frame as sent by a client
+     */
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![if fin { 0x80 | opcode } else { opcode }];
+        if payload.len() < 126 {
+            frame.push(0x80 | payload.len() as u8);
+        } else {
+            frame.push(0x80 | 126);
+            frame.push((payload.len() >> 8) as u8);
+            frame.push(payload.len() as u8);
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(index, byte)| byte ^ mask[index % 4]));
+        frame
+    }
+
+    fn handshake() -> Vec<u8> {
+        b"GET /rori HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+          Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+            .to_vec()
+    }
+
+    fn websocket(input: Vec<u8>, max_message_size: usize) -> WebSocket<Duplex> {
+        WebSocket::new(Duplex {
+                           input: Cursor::new(input),
+                           output: Vec::new(),
+                       },
+                       max_message_size)
+    }
+
+    #[test]
+    /**
+     * test the example of RFC 6455
+     */
+    fn test_accept_key() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    /**
+     * test the handshake and messages sent by a client
+     */
+    fn test_messages() {
+        let long = "a".repeat(300);
+        let mut input = handshake();
+        input.extend(client_frame(true, 0x1, b"hello"));
+        input.extend(client_frame(false, 0x1, b"hel"));
+        // Control frames can be sent between fragments
+        input.extend(client_frame(true, 0x9, b"ping"));
+        input.extend(client_frame(true, 0x0, b"lo"));
+        input.extend(client_frame(true, 0x1, long.as_bytes()));
+        input.extend(client_frame(true, 0x8, &[0x03, 0xE8]));
+        let mut socket = websocket(input, 1024);
+
+        assert_eq!(socket.poll().unwrap(), Some(Message::Text(String::from("hello"))));
+        assert_eq!(socket.poll().unwrap(), Some(Message::Text(String::from("hello"))));
+        assert_eq!(socket.poll().unwrap(), Some(Message::Text(long)));
+        assert_eq!(socket.poll().unwrap(), Some(Message::Close));
+        socket.send_text("reply").unwrap();
+
+        let output = socket.get_mut().output.clone();
+        let response = String::from_utf8_lossy(&output).into_owned();
+        assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        let frames = &output[output.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4..];
+        // pong, close, then the text
+        assert_eq!(frames,
+                   &[&[0x8A, 4][..], b"ping", &[0x88, 2, 0x03, 0xE8, 0x81, 5], b"reply"].concat()[..]);
+    }
+
+    #[test]
+    /**
+     * test that invalid clients are refused
+     */
+    fn test_invalid_messages() {
+        let mut socket = websocket(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n".to_vec(), 1024);
+        match socket.poll() {
+            Err(FrameError::InvalidHeader(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert!(socket.get_mut().output.starts_with(b"HTTP/1.1 400"));
+
+        // Busy servers answer before the handshake
+        let mut socket = websocket(handshake(), 1024);
+        socket.refuse().unwrap();
+        assert!(socket.get_mut().output.starts_with(b"HTTP/1.1 503"));
+
+        let mut input = handshake();
+        input.extend(client_frame(true, 0x1, &[b'a'; 100]));
+        match websocket(input, 10).poll() {
+            Err(FrameError::TooLarge(100)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        // The size is checked before waiting for the payload
+        let mut input = handshake();
+        input.extend(&[0x81, 0x80 | 127, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        match websocket(input, 1024).poll() {
+            Err(FrameError::TooLarge(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let mut input = handshake();
+        input.extend(client_frame(true, 0x1, &[0xFF, 0xFE]));
+        match websocket(input, 1024).poll() {
+            Err(FrameError::InvalidUtf8) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        // Frames sent by clients must be masked
+        let mut input = handshake();
+        input.extend(&[0x81, 0x02, b'h', b'i']);
+        match websocket(input, 1024).poll() {
+            Err(FrameError::InvalidHeader(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        // Control frames have at most 125 bytes and are not fragmented
+        for frame in &[client_frame(true, 0x9, &[b'a'; 126]), client_frame(false, 0x9, b"ping")] {
+            let mut input = handshake();
+            input.extend(frame);
+            match websocket(input, 1024).poll() {
+                Err(FrameError::InvalidHeader(_)) => {}
+                other => panic!("Unexpected result {:?}", other),
+            }
+        }
+    }
+}
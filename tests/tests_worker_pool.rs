@@ -0,0 +1,63 @@
+extern crate main;
+
+#[cfg(test)]
+mod tests_worker_pool {
+    use main::core::worker_pool::*;
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    /**
+     * test if jobs are rejected when the queue is full
+     */
+    fn test_reject_overflow() {
+        let (release_sender, release_receiver) = channel::<()>();
+        let release_receiver = Arc::new(Mutex::new(release_receiver));
+        let (done_sender, done_receiver) = channel::<u32>();
+        let done_sender = Arc::new(Mutex::new(done_sender));
+        let stats = Arc::new(PoolStats::default());
+        let pool = WorkerPool::new(1, 1, OverflowPolicy::Reject, stats.clone(), move |job: u32| {
+            let _ = release_receiver.lock().unwrap().recv();
+            let _ = done_sender.lock().unwrap().send(job);
+        });
+
+        assert_eq!(pool.submit(1), Ok(()));
+        // Wait for the worker to take the first job
+        while stats.snapshot().active == 0 {
+            ::std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(pool.submit(2), Ok(()));
+        assert_eq!(pool.submit(3), Err(3));
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.queue_depth, 1);
+        assert_eq!(snapshot.rejected, 1);
+
+        release_sender.send(()).unwrap();
+        release_sender.send(()).unwrap();
+        assert_eq!(done_receiver.recv().unwrap(), 1);
+        assert_eq!(done_receiver.recv().unwrap(), 2);
+    }
+
+    #[test]
+    /**
+     * test if a panicking job doesn't kill the worker
+     */
+    fn test_panicking_job() {
+        let (done_sender, done_receiver) = channel::<u32>();
+        let done_sender = Arc::new(Mutex::new(done_sender));
+        let pool = WorkerPool::new(1,
+                                   4,
+                                   OverflowPolicy::Block,
+                                   Arc::new(PoolStats::default()),
+                                   move |job: u32| {
+                                       if job == 0 {
+                                           panic!("job failed");
+                                       }
+                                       let _ = done_sender.lock().unwrap().send(job);
+                                   });
+        assert_eq!(pool.submit(0), Ok(()));
+        assert_eq!(pool.submit(1), Ok(()));
+        assert_eq!(done_receiver.recv().unwrap(), 1);
+    }
+}